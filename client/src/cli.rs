@@ -0,0 +1,410 @@
+// One-shot command-line subcommands for scripting and editor integrations.
+// They talk to the same server as the daemon but never touch the local clipboard;
+// `pause`, `resume` and `send-held` instead steer the running daemon over its control socket.
+
+use futures_util::StreamExt;
+use std::io::{IsTerminal, Read, Write};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+
+use crate::config::Config;
+use crate::{ClipboardData, ClipboardMessage, ServerNotice};
+
+type CliResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+pub const USAGE: &str = "\
+Usage: clipboard-client [--url=URL] [COMMAND]
+
+Without a command the client runs as a background daemon.
+
+Commands:
+  push [--html FILE] [--rtf FILE] [--image FILE] [--ttl DURATION]
+                      Send stdin as plain text, optionally with rich formats;
+                      with --ttl the server clears it again after DURATION
+  pull [--format text|html|rtf|image]
+                      Write the current server clipboard to stdout
+  watch [--json]      Print every update as it arrives
+  history [--json]    List recent updates, newest first
+  clear               Remove the current clipboard from the server and every device
+  status              Show server health and the current clipboard summary
+  pause [--for DURATION]
+                      Stop the running daemon syncing, e.g. --for 15m (s, m, h)
+  resume              Resume syncing after a pause
+  send-held           Send clipboard content held back as sensitive
+  help                Show this message
+
+Options:
+  --url=URL           Server URL (default: config file, then CLIPBOARD_SERVER_URL)
+                      Other settings come from the config file (see README)
+  --profile=NAME      Use this profile from the config file instead of the active one
+  --headless          Run the daemon without a tray icon
+  --settings          Open the settings window";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PullFormat { Text, Html, Rtf, Image }
+
+#[derive(Debug, Clone)]
+pub enum CliCommand {
+    Push { html: Option<String>, rtf: Option<String>, image: Option<String>, ttl: Option<Duration> },
+    Pull { format: PullFormat },
+    Watch { json: bool },
+    History { json: bool },
+    Clear,
+    Status,
+    Pause { resume_after: Option<Duration> },
+    Resume,
+    SendHeld,
+    Help,
+}
+
+// Parse the subcommand, if any. Global `--flag=value` options are skipped here.
+pub fn parse(args: &[String]) -> Result<Option<CliCommand>, String> {
+    let mut rest = args.iter().skip(1).filter(|a| !(a.starts_with("--url=") || a.starts_with("--profile=") || *a == "--headless" || *a == "--connected" || *a == "--disconnected"));
+    let Some(command) = rest.next() else { return Ok(None) };
+    let rest: Vec<&String> = rest.collect();
+
+    let flag = |name: &str| rest.iter().any(|a| *a == name);
+    let value = |name: &str| -> Result<Option<String>, String> {
+        let prefix = format!("{}=", name);
+        for (i, a) in rest.iter().enumerate() {
+            if let Some(v) = a.strip_prefix(&prefix) { return Ok(Some(v.to_string())); }
+            if *a == name {
+                return rest.get(i + 1).map(|v| Some(v.to_string())).ok_or_else(|| format!("{} requires a value", name));
+            }
+        }
+        Ok(None)
+    };
+    let reject_unknown = |known: &[&str]| -> Result<(), String> {
+        let mut skip_next = false;
+        for a in &rest {
+            if skip_next { skip_next = false; continue; }
+            let name = a.split('=').next().unwrap_or(a);
+            if !known.contains(&name) { return Err(format!("unexpected argument '{}' for '{}'", a, command)); }
+            skip_next = !a.contains('=') && name != "--json";
+        }
+        Ok(())
+    };
+
+    let cmd = match command.as_str() {
+        "push" => {
+            reject_unknown(&["--html", "--rtf", "--image", "--ttl"])?;
+            let ttl = value("--ttl")?.as_deref().map(parse_duration).transpose()?;
+            CliCommand::Push { html: value("--html")?, rtf: value("--rtf")?, image: value("--image")?, ttl }
+        }
+        "pull" => {
+            reject_unknown(&["--format"])?;
+            let format = match value("--format")?.as_deref() {
+                None | Some("text") => PullFormat::Text,
+                Some("html") => PullFormat::Html,
+                Some("rtf") => PullFormat::Rtf,
+                Some("image") => PullFormat::Image,
+                Some(other) => return Err(format!("unknown format '{}' (expected text, html, rtf or image)", other)),
+            };
+            CliCommand::Pull { format }
+        }
+        "watch" => { reject_unknown(&["--json"])?; CliCommand::Watch { json: flag("--json") } }
+        "history" => { reject_unknown(&["--json"])?; CliCommand::History { json: flag("--json") } }
+        "clear" => { reject_unknown(&[])?; CliCommand::Clear }
+        "status" => { reject_unknown(&[])?; CliCommand::Status }
+        "pause" => {
+            reject_unknown(&["--for"])?;
+            CliCommand::Pause { resume_after: value("--for")?.as_deref().map(parse_duration).transpose()? }
+        }
+        "resume" => { reject_unknown(&[])?; CliCommand::Resume }
+        "send-held" => { reject_unknown(&[])?; CliCommand::SendHeld }
+        "help" | "--help" | "-h" => CliCommand::Help,
+        other if other.starts_with('-') => return Err(format!("unknown option '{}'", other)),
+        other => return Err(format!("unknown command '{}'", other)),
+    };
+    Ok(Some(cmd))
+}
+
+// "90", "90s", "15m" or "2h".
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid duration '{}' (expected e.g. 30s, 15m or 2h)", text);
+    let (number, unit) = text.split_at(text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len()));
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let secs = match unit {
+        "" | "s" => Some(number),
+        "m" => number.checked_mul(60),
+        "h" => number.checked_mul(3600),
+        _ => return Err(invalid()),
+    };
+    let secs = secs.ok_or_else(|| format!("duration '{}' is too long", text))?;
+    if secs == 0 { return Err(invalid()); }
+    Ok(Duration::from_secs(secs))
+}
+
+pub async fn run(cmd: CliCommand, config: &Config) -> CliResult {
+    let base = config.active().server_url.trim_end_matches('/');
+    let http = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .default_headers(crate::request_headers(config, config.active())?)
+        .build()?;
+    match cmd {
+        CliCommand::Push { html, rtf, image, ttl } => push(&http, base, html, rtf, image, ttl).await,
+        CliCommand::Pull { format } => pull(&http, base, format).await,
+        CliCommand::Watch { json } => watch(base, config, json).await,
+        CliCommand::History { json } => history(&http, base, json).await,
+        CliCommand::Clear => clear(&http, base).await,
+        CliCommand::Status => status(&http, base).await,
+        CliCommand::Help => { println!("{}", USAGE); Ok(()) }
+        CliCommand::Pause { resume_after: Some(d) } => crate::control::request(&format!("pause {}", d.as_secs())).await,
+        CliCommand::Pause { resume_after: None } => crate::control::request("pause").await,
+        CliCommand::Resume => crate::control::request("resume").await,
+        CliCommand::SendHeld => crate::control::request("send-held").await,
+    }
+}
+
+async fn push(http: &reqwest::Client, base: &str, html: Option<String>, rtf: Option<String>, image: Option<String>, ttl: Option<Duration>) -> CliResult {
+    use base64::Engine;
+
+    let read_file = |path: &str| std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e));
+    let html = html.as_deref().map(read_file).transpose()?;
+    let rtf = rtf.as_deref().map(read_file).transpose()?;
+    let image = match image {
+        Some(path) => Some(base64::engine::general_purpose::STANDARD.encode(std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?)),
+        None => None,
+    };
+
+    // With a rich format given on the command line an interactive stdin is not waited for
+    let mut content = String::new();
+    let has_rich = html.is_some() || rtf.is_some() || image.is_some();
+    if !(has_rich && std::io::stdin().is_terminal()) {
+        std::io::stdin().read_to_string(&mut content)?;
+    }
+
+    let content_type = match (&html, &rtf, &image) {
+        (Some(_), Some(_), _) => "mixed",
+        (Some(_), None, _) => "html",
+        (None, Some(_), _) => "rtf",
+        (None, None, Some(_)) if content.is_empty() => "image",
+        (None, None, Some(_)) => "mixed",
+        (None, None, None) => "text",
+    }.to_string();
+    let data = ClipboardData {
+        content,
+        html,
+        rtf,
+        image,
+        content_type,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        seq: None,
+        ttl: ttl.map(|d| d.as_secs()),
+        source_app: None,
+        extra_formats: Default::default(),
+        fingerprint: None,
+    };
+
+    http.post(format!("{}/api/clipboard", base)).json(&data).send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn pull(http: &reqwest::Client, base: &str, format: PullFormat) -> CliResult {
+    use base64::Engine;
+
+    let data: ClipboardData = http.get(format!("{}/api/clipboard", base)).send().await?.error_for_status()?.json().await?;
+    let missing = |name: &str| format!("clipboard has no {} content", name);
+    let bytes = match format {
+        PullFormat::Text => data.content.into_bytes(),
+        PullFormat::Html => data.html.ok_or_else(|| missing("HTML"))?.into_bytes(),
+        PullFormat::Rtf => data.rtf.ok_or_else(|| missing("RTF"))?.into_bytes(),
+        PullFormat::Image => {
+            let image = data.image.ok_or_else(|| missing("image"))?;
+            // Accept both bare base64 and data: URLs (as stored by the web UI)
+            let encoded = image.split_once(',').filter(|_| image.starts_with("data:")).map_or(image.as_str(), |(_, b)| b);
+            base64::engine::general_purpose::STANDARD.decode(encoded.trim())?
+        }
+    };
+    let mut stdout = std::io::stdout().lock();
+    stdout.write_all(&bytes)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+async fn watch(base: &str, config: &Config, json: bool) -> CliResult {
+    let mut reconnect_delay = Duration::from_secs(1);
+    loop {
+        let request = crate::websocket_request(base, config, config.active())?;
+        match connect_async(request).await {
+            Ok((ws_stream, _)) => {
+                reconnect_delay = Duration::from_secs(1);
+                let (_, mut ws_receiver) = ws_stream.split();
+                while let Some(msg) = ws_receiver.next().await {
+                    let text = match msg {
+                        Ok(Message::Text(text)) => text,
+                        Ok(Message::Close(_)) | Err(_) => break,
+                        _ => continue,
+                    };
+                    if let Ok(clipboard_msg) = serde_json::from_str::<ClipboardMessage>(&text) {
+                        if clipboard_msg.msg_type != "clipboard_update" { continue; }
+                        let mut stdout = std::io::stdout().lock();
+                        if json {
+                            writeln!(stdout, "{}", serde_json::to_string(&clipboard_msg.data)?)?;
+                        } else {
+                            writeln!(stdout, "{}", clipboard_msg.data.content)?;
+                        }
+                        stdout.flush()?;
+                    } else if let Ok(notice) = serde_json::from_str::<ServerNotice>(&text) {
+                        if notice.msg_type == "server_shutdown" {
+                            if let Some(secs) = notice.reconnect_after { reconnect_delay = Duration::from_secs(secs); }
+                        }
+                    }
+                }
+                eprintln!("Connection closed, reconnecting in {:?}", reconnect_delay);
+            }
+            Err(e) => eprintln!("Failed to connect: {}, retrying in {:?}", e, reconnect_delay),
+        }
+        tokio::time::sleep(reconnect_delay).await;
+        reconnect_delay = std::cmp::min(reconnect_delay * 2, Duration::from_secs(60));
+    }
+}
+
+async fn history(http: &reqwest::Client, base: &str, json: bool) -> CliResult {
+    let resp = http.get(format!("{}/api/clipboard/history", base)).send().await?.error_for_status()?;
+    let entries: Vec<ClipboardData> = resp.json().await?;
+    let mut stdout = std::io::stdout().lock();
+    if json {
+        writeln!(stdout, "{}", serde_json::to_string_pretty(&entries)?)?;
+        return Ok(());
+    }
+    for entry in entries {
+        let seq = entry.seq.map_or_else(|| "-".to_string(), |s| s.to_string());
+        writeln!(stdout, "{:>6}  {:<6}  {}", seq, entry.content_type, preview(&entry))?;
+    }
+    Ok(())
+}
+
+async fn clear(http: &reqwest::Client, base: &str) -> CliResult {
+    let resp = http.delete(format!("{}/api/clipboard", base)).send().await?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err("the server clipboard is already empty".into());
+    }
+    resp.error_for_status()?;
+    Ok(())
+}
+
+async fn status(http: &reqwest::Client, base: &str) -> CliResult {
+    #[derive(serde::Deserialize)]
+    struct Health { status: String, version: String, uptime_seconds: u64 }
+
+    println!("Server:  {}", base);
+    let health: Health = match http.get(format!("{}/healthz", base)).send().await {
+        Ok(resp) => resp.error_for_status()?.json().await?,
+        Err(e) => return Err(format!("server unreachable: {}", e).into()),
+    };
+    println!("Status:  {} (version {}, up {}s)", health.status, health.version, health.uptime_seconds);
+    let data: ClipboardData = http.get(format!("{}/api/clipboard", base)).send().await?.error_for_status()?.json().await?;
+    if data.timestamp == 0 && data.content.is_empty() {
+        println!("Current: empty");
+    } else {
+        println!("Current: {} ({} chars) {}", data.content_type, data.content.chars().count(), preview(&data));
+    }
+    Ok(())
+}
+
+// Single-line summary of an entry for terminal listings.
+fn preview(data: &ClipboardData) -> String {
+    if data.content.is_empty() && data.image.is_some() { return "[image]".to_string(); }
+    let line: String = data.content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if line.chars().count() > 60 { format!("{}…", line.chars().take(60).collect::<String>()) } else { line }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Option<CliCommand>, String> {
+        let args: Vec<String> = std::iter::once("clipboard-client").chain(args.iter().copied()).map(String::from).collect();
+        parse(&args)
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        for invalid in ["", "0", "0m", "m", "15d", "1.5h", "-5s", "15 m", "99999999999999999999"] {
+            assert!(parse_duration(invalid).is_err(), "{:?}", invalid);
+        }
+        // Too long once converted to seconds
+        assert!(parse_duration("99999999999999999h").is_err());
+        assert!(parse_duration("999999999999999999m").is_err());
+    }
+
+    #[test]
+    fn no_command_runs_the_daemon() {
+        assert!(parse_args(&[]).unwrap().is_none());
+        assert!(parse_args(&["--url=http://server:8080", "--headless"]).unwrap().is_none());
+    }
+
+    #[test]
+    fn push() {
+        let Some(CliCommand::Push { html, rtf, image, ttl }) = parse_args(&["push"]).unwrap() else { panic!() };
+        assert_eq!((html, rtf, image, ttl), (None, None, None, None));
+        let cmd = parse_args(&["--profile=work", "push", "--html", "a.html", "--rtf=a.rtf", "--image", "a.png", "--ttl", "30s"]).unwrap();
+        let Some(CliCommand::Push { html, rtf, image, ttl }) = cmd else { panic!() };
+        assert_eq!(html.as_deref(), Some("a.html"));
+        assert_eq!(rtf.as_deref(), Some("a.rtf"));
+        assert_eq!(image.as_deref(), Some("a.png"));
+        assert_eq!(ttl, Some(Duration::from_secs(30)));
+        assert!(parse_args(&["push", "--ttl"]).is_err());
+        assert!(parse_args(&["push", "--ttl", "99999999999999999h"]).is_err());
+        assert!(parse_args(&["push", "--json"]).is_err());
+    }
+
+    #[test]
+    fn pull() {
+        let format = |args: &[&str]| match parse_args(args) {
+            Ok(Some(CliCommand::Pull { format })) => Ok(format),
+            Ok(other) => panic!("{:?}", other),
+            Err(e) => Err(e),
+        };
+        assert_eq!(format(&["pull"]), Ok(PullFormat::Text));
+        assert_eq!(format(&["pull", "--format", "text"]), Ok(PullFormat::Text));
+        assert_eq!(format(&["pull", "--format=html"]), Ok(PullFormat::Html));
+        assert_eq!(format(&["pull", "--format", "rtf"]), Ok(PullFormat::Rtf));
+        assert_eq!(format(&["pull", "--format", "image"]), Ok(PullFormat::Image));
+        assert!(format(&["pull", "--format", "pdf"]).is_err());
+        assert!(format(&["pull", "--html"]).is_err());
+    }
+
+    #[test]
+    fn watch_and_history() {
+        assert!(matches!(parse_args(&["watch"]), Ok(Some(CliCommand::Watch { json: false }))));
+        assert!(matches!(parse_args(&["watch", "--json"]), Ok(Some(CliCommand::Watch { json: true }))));
+        assert!(matches!(parse_args(&["history"]), Ok(Some(CliCommand::History { json: false }))));
+        assert!(matches!(parse_args(&["history", "--json"]), Ok(Some(CliCommand::History { json: true }))));
+        assert!(parse_args(&["watch", "--format", "html"]).is_err());
+    }
+
+    #[test]
+    fn pause() {
+        assert!(matches!(parse_args(&["pause"]), Ok(Some(CliCommand::Pause { resume_after: None }))));
+        let Ok(Some(CliCommand::Pause { resume_after })) = parse_args(&["pause", "--for", "15m"]) else { panic!() };
+        assert_eq!(resume_after, Some(Duration::from_secs(900)));
+        assert!(parse_args(&["pause", "--for", "soon"]).is_err());
+        assert!(parse_args(&["pause", "--for=99999999999999999h"]).is_err());
+    }
+
+    #[test]
+    fn commands_without_options() {
+        assert!(matches!(parse_args(&["clear"]), Ok(Some(CliCommand::Clear))));
+        assert!(matches!(parse_args(&["status"]), Ok(Some(CliCommand::Status))));
+        assert!(matches!(parse_args(&["resume"]), Ok(Some(CliCommand::Resume))));
+        assert!(matches!(parse_args(&["send-held"]), Ok(Some(CliCommand::SendHeld))));
+        for help in ["help", "--help", "-h"] {
+            assert!(matches!(parse_args(&[help]), Ok(Some(CliCommand::Help))));
+        }
+        for command in ["clear", "status", "resume", "send-held"] {
+            assert!(parse_args(&[command, "--json"]).is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn unknown_commands_and_options() {
+        assert_eq!(parse_args(&["paste"]).unwrap_err(), "unknown command 'paste'");
+        assert_eq!(parse_args(&["--verbose"]).unwrap_err(), "unknown option '--verbose'");
+    }
+}
@@ -0,0 +1,266 @@
+// Platform-independent sync policy on top of a `ClipboardBackend`: which local reads are new copies
+// to send, and which incoming updates are our own copies coming back. Content is compared by
+// fingerprint, so every platform behaves the same; backends only describe what they can read back.
+
+use clipboard_common::Fingerprint;
+
+use super::ClipboardBackend;
+use crate::ClipboardData;
+
+// Local copies within this many seconds of an applied server update's timestamp are not sent
+const SERVER_UPDATE_GRACE_SECS: u64 = 5;
+
+pub struct SyncEngine<B> {
+    backend: B,
+    // Content last seen on the local clipboard, whether copied here or applied from a server
+    last_content_fingerprint: Option<Fingerprint>,
+    last_server_timestamp: Option<u64>,
+    // Content last sent, recognised when the server echoes it back, and the local copy it came from
+    last_sent: Option<(Fingerprint, Fingerprint)>,
+}
+
+impl<B: ClipboardBackend> SyncEngine<B> {
+    pub fn new(backend: B) -> Self {
+        Self { backend, last_content_fingerprint: None, last_server_timestamp: None, last_sent: None }
+    }
+
+    pub fn backend(&self) -> &B { &self.backend }
+
+    pub fn backend_mut(&mut self) -> &mut B { &mut self.backend }
+
+    pub fn get_clipboard_data(&self) -> Result<ClipboardData, Box<dyn std::error::Error + Send + Sync>> {
+        self.backend.get_clipboard_data()
+    }
+
+    // Whether `data`, just read from the local clipboard, is a new local copy
+    pub fn has_content_changed(&mut self, data: &ClipboardData) -> bool {
+        let fingerprint = data.content_fingerprint();
+        if self.last_content_fingerprint == Some(fingerprint) { return false; }
+        if self.last_server_timestamp.is_some_and(|ts| data.timestamp <= ts + SERVER_UPDATE_GRACE_SECS) { return false; }
+        self.last_content_fingerprint = Some(fingerprint);
+        true
+    }
+
+    // `data` is what the outgoing pipeline made of the local copy last seen by `has_content_changed`
+    pub fn mark_content_as_sent(&mut self, data: &ClipboardData) {
+        let sent = data.content_fingerprint();
+        self.last_sent = Some((sent, self.last_content_fingerprint.unwrap_or(sent)));
+    }
+
+    // Fingerprint of the local copy that `data`, echoed by the server, was sent from; None when
+    // `data` is not our own content returned
+    pub fn local_copy_of(&self, data: &ClipboardData) -> Option<Fingerprint> {
+        let (sent, local) = self.last_sent?;
+        (sent == data.content_fingerprint()).then_some(local)
+    }
+
+    // Remembered as the local content, so the next poll does not send it back.
+    // Returns the fingerprint of what the local clipboard now reads back.
+    pub fn set_clipboard_data_from_server(&mut self, data: &ClipboardData) -> Result<Fingerprint, Box<dyn std::error::Error + Send + Sync>> {
+        self.backend.set_clipboard_data(data)?;
+        let local = self.backend.readable(data).content_fingerprint();
+        self.last_content_fingerprint = Some(local);
+        self.last_server_timestamp = Some(data.timestamp);
+        Ok(local)
+    }
+
+    // Whether the local clipboard still holds the content with fingerprint `local`
+    pub fn holds(&self, local: Fingerprint) -> bool {
+        self.backend.get_clipboard_data().is_ok_and(|current| current.content_fingerprint() == local)
+    }
+
+    pub fn clear(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.backend.clear()?;
+        self.last_content_fingerprint = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, Formats, Stage};
+    use crate::pipeline::Pipeline;
+
+    // In-memory clipboard with a settable clock; `text_only` reads back like the Windows backend
+    #[derive(Default)]
+    struct Fake {
+        clipboard: Option<ClipboardData>,
+        now: u64,
+        text_only: bool,
+    }
+
+    impl ClipboardBackend for Fake {
+        fn get_clipboard_data(&self) -> Result<ClipboardData, Box<dyn std::error::Error + Send + Sync>> {
+            let data = self.clipboard.clone().ok_or("clipboard is empty")?;
+            Ok(ClipboardData { timestamp: self.now, ..data })
+        }
+        fn set_clipboard_data(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            self.clipboard = Some(self.readable(data));
+            Ok(())
+        }
+        fn readable(&self, data: &ClipboardData) -> ClipboardData {
+            if !self.text_only { return data.clone(); }
+            ClipboardData { html: None, rtf: None, image: None, content_type: "text".to_string(), ..data.clone() }
+        }
+        fn is_concealed(&self) -> bool { false }
+        fn source_app(&self) -> Option<String> { None }
+        fn configure(&mut self, _formats: &Formats) {}
+        fn clear(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            self.clipboard = None;
+            Ok(())
+        }
+    }
+
+    fn device(now: u64) -> SyncEngine<Fake> {
+        SyncEngine::new(Fake { now, ..Fake::default() })
+    }
+
+    fn copy(engine: &mut SyncEngine<Fake>, content: &str) {
+        engine.backend_mut().clipboard = Some(ClipboardData::text(content));
+    }
+
+    fn advance(engine: &mut SyncEngine<Fake>, secs: u64) {
+        engine.backend_mut().now += secs;
+    }
+
+    // One monitor tick: what the device would send, if anything
+    fn poll(engine: &mut SyncEngine<Fake>) -> Option<ClipboardData> {
+        let data = engine.get_clipboard_data().ok()?;
+        if !engine.has_content_changed(&data) { return None; }
+        engine.mark_content_as_sent(&data);
+        Some(data)
+    }
+
+    // An update arriving over the WebSocket: whether it was applied
+    fn deliver(engine: &mut SyncEngine<Fake>, data: &ClipboardData) -> bool {
+        if engine.local_copy_of(data).is_some() { return false; }
+        engine.set_clipboard_data_from_server(data).unwrap();
+        true
+    }
+
+    // Poll every device until nothing more is sent, relaying each update to all of them like the
+    // server does (the sender included). Returns everything that was sent.
+    fn settle(devices: &mut [SyncEngine<Fake>]) -> Vec<ClipboardData> {
+        let mut sent = Vec::new();
+        for _ in 0..10 {
+            let round: Vec<_> = devices.iter_mut().filter_map(poll).collect();
+            if round.is_empty() { return sent; }
+            for data in &round {
+                devices.iter_mut().for_each(|device| { deliver(device, data); });
+            }
+            devices.iter_mut().for_each(|device| advance(device, 10));
+            sent.extend(round);
+        }
+        panic!("devices kept sending: {:?}", sent.iter().map(|d| &d.content).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn local_copy_is_sent_once() {
+        let mut a = device(100);
+        copy(&mut a, "x");
+        assert_eq!(poll(&mut a).unwrap().content, "x");
+        assert!(poll(&mut a).is_none());
+        copy(&mut a, "y");
+        assert_eq!(poll(&mut a).unwrap().content, "y");
+    }
+
+    #[test]
+    fn applied_update_is_not_sent_back() {
+        let mut b = device(100);
+        assert!(deliver(&mut b, &ClipboardData::text("x")));
+        assert_eq!(b.get_clipboard_data().unwrap().content, "x");
+        assert!(poll(&mut b).is_none());
+    }
+
+    #[test]
+    fn own_update_echoed_by_the_server_is_ignored() {
+        let mut a = device(100);
+        copy(&mut a, "x");
+        let sent = poll(&mut a).unwrap();
+        assert!(!deliver(&mut a, &sent));
+        // Normalisation differences between platforms do not defeat the check
+        assert!(!deliver(&mut a, &ClipboardData { content: "x\r\n".to_string(), ..sent }));
+        assert!(deliver(&mut a, &ClipboardData::text("z")));
+    }
+
+    #[test]
+    fn copies_do_not_ping_pong_between_devices() {
+        let mut devices = [device(100), device(100), device(100)];
+        copy(&mut devices[0], "x");
+        let sent = settle(&mut devices);
+        assert_eq!(sent.iter().map(|d| d.content.as_str()).collect::<Vec<_>>(), ["x"]);
+
+        copy(&mut devices[2], "y");
+        let sent = settle(&mut devices);
+        assert_eq!(sent.iter().map(|d| d.content.as_str()).collect::<Vec<_>>(), ["y"]);
+        assert!(devices.iter().all(|device| device.get_clipboard_data().unwrap().content == "y"));
+    }
+
+    #[test]
+    fn lossy_backend_does_not_bounce_rich_content() {
+        let rich = ClipboardData { html: Some("<b>x</b>".to_string()), content_type: "html".to_string(), ..ClipboardData::text("x") };
+        let a = SyncEngine::new(Fake { clipboard: Some(rich), now: 100, ..Fake::default() });
+        let b = SyncEngine::new(Fake { now: 100, text_only: true, ..Fake::default() });
+        let mut devices = [a, b];
+        assert_eq!(settle(&mut devices).len(), 1);
+        assert_eq!(devices[1].get_clipboard_data().unwrap().html, None);
+    }
+
+    #[test]
+    fn copy_right_after_an_update_waits_for_the_grace_period() {
+        let mut b = device(100);
+        deliver(&mut b, &ClipboardData { timestamp: 100, ..ClipboardData::text("x") });
+        copy(&mut b, "y");
+        advance(&mut b, SERVER_UPDATE_GRACE_SECS);
+        assert!(poll(&mut b).is_none());
+        advance(&mut b, 1);
+        assert_eq!(poll(&mut b).unwrap().content, "y");
+    }
+
+    #[test]
+    fn holds_compares_what_the_backend_can_read_back() {
+        let mut b = SyncEngine::new(Fake { now: 100, text_only: true, ..Fake::default() });
+        let update = ClipboardData { rtf: Some("{\\rtf1 x}".to_string()), content_type: "rtf".to_string(), ..ClipboardData::text("x") };
+        let local = b.set_clipboard_data_from_server(&update).unwrap();
+        assert!(b.holds(local));
+        b.clear().unwrap();
+        assert!(!b.holds(local));
+        // After a clear the same content counts as new again
+        copy(&mut b, "x");
+        assert!(poll(&mut b).is_some());
+    }
+
+    #[test]
+    fn update_changed_by_the_incoming_pipeline_can_be_cleared() {
+        let mut b = device(100);
+        let update = ClipboardData {
+            html: Some("<p onclick=\"steal()\">x</p>".to_string()),
+            content_type: "html".to_string(),
+            seq: Some(7),
+            ..ClipboardData::text("x")
+        };
+        let applied = Pipeline::incoming(&Config::default()).unwrap().run(update.clone()).unwrap();
+        // Sanitised and completed with RTF, so the update as sent no longer matches the clipboard
+        assert_ne!(applied.content_fingerprint(), update.content_fingerprint());
+        let local = b.set_clipboard_data_from_server(&applied).unwrap();
+        assert!(b.holds(local));
+        b.clear().unwrap();
+        assert!(!b.holds(local));
+    }
+
+    #[test]
+    fn own_copy_changed_by_the_outgoing_pipeline_can_be_cleared() {
+        let mut a = device(100);
+        copy(&mut a, "x  ");
+        let mut config = Config::default();
+        config.pipeline.outgoing = vec![Stage::TrimWhitespace];
+        let sent = Pipeline::outgoing(&config).unwrap().run(poll(&mut a).unwrap()).unwrap();
+        a.mark_content_as_sent(&sent);
+        let echo = ClipboardData { seq: Some(3), ..sent };
+        let local = a.local_copy_of(&echo).unwrap();
+        assert!(a.holds(local));
+        a.clear().unwrap();
+        assert!(!a.holds(local));
+    }
+}
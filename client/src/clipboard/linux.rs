@@ -1,35 +1,57 @@
+use crate::config::Formats;
 use crate::ClipboardData;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tracing::debug;
 use super::ClipboardBackend;
 
 use wl_clipboard_rs::{
     copy::{copy_multi, MimeSource, MimeType as CopyMimeType, Options, Source},
-    paste::{get_contents, ClipboardType, Seat}
+    paste::{get_contents, get_mime_types, ClipboardType, Seat}
 };
 
+// MIME types RTF is offered under; LibreOffice uses text/rtf and text/richtext, others application/rtf
+const RTF_MIME_TYPES: &[&str] = &["text/rtf", "application/rtf", "text/richtext"];
+
+// MIME types password managers offer alongside a copied secret
+const CONCEALED_MIME_TYPES: &[&str] = &[
+    "x-kde-passwordManagerHint",
+    "application/x-nspasteboard-concealed-type",
+    "ExcludeClipboardContentFromMonitorProcessing",
+];
+
+// Passthrough payloads are read again at least this often while the rest of the clipboard is unchanged
+const EXTRA_FORMATS_MAX_AGE: Duration = Duration::from_secs(2);
+
 pub struct LinuxClipboardManager {
-    last_content_hash: Option<u64>,
-    last_server_timestamp: Option<u64>,
-    last_sent_hash: Option<u64>,
+    // Which extra MIME types are passed through, and up to what size
+    formats: Formats,
+    // Saves reading up to `max_mime_bytes` per passthrough type on every poll
+    extra_formats_cache: RefCell<Option<ExtraFormatsCache>>,
+}
+
+// Passthrough payloads of the last read
+struct ExtraFormatsCache {
+    // Hash of the offered types and the text, HTML and RTF read alongside them
+    key: u64,
+    read_at: Instant,
+    formats: BTreeMap<String, String>,
 }
 
 impl LinuxClipboardManager {
     pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        Ok(Self { last_content_hash: None, last_server_timestamp: None, last_sent_hash: None })
+        Ok(Self { formats: Formats::default(), extra_formats_cache: RefCell::new(None) })
     }
 
-    fn calculate_content_hash(data: &ClipboardData) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut hasher = DefaultHasher::new();
-        let normalized_content = if data.content.trim().starts_with('<') && data.content.trim().ends_with('>') {
-            data.content.trim().to_string()
-        } else { data.content.clone() };
-        normalized_content.hash(&mut hasher);
-        if let Some(ref html) = data.html { if html.trim() != normalized_content.trim() { html.hash(&mut hasher); } }
-        if let Some(ref rtf) = data.rtf { rtf.hash(&mut hasher); }
-        hasher.finish()
+    // Passthrough payloads for the current clipboard, from the cache while `key` still matches
+    fn get_extra_formats_cached(&self, offered: &HashSet<String>, key: u64) -> BTreeMap<String, String> {
+        if let Some(cache) = &*self.extra_formats_cache.borrow() {
+            if cache.key == key && cache.read_at.elapsed() < EXTRA_FORMATS_MAX_AGE { return cache.formats.clone(); }
+        }
+        let formats = self.get_extra_formats(offered);
+        *self.extra_formats_cache.borrow_mut() = Some(ExtraFormatsCache { key, read_at: Instant::now(), formats: formats.clone() });
+        formats
     }
 
     fn get_text_content(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
@@ -46,57 +68,170 @@ impl LinuxClipboardManager {
             Err(e) => { debug!("No HTML content available: {}", e); Err(e.into()) }
         }
     }
-    fn get_rtf_content(&self) -> Result<String, Box<dyn std::error::Error + Send + Sync>> { Err("RTF not supported on Linux yet".into()) }
+    // The first RTF type offered, or any of them when the offered types are unknown
+    fn get_rtf_content(&self, offered: Option<&HashSet<String>>) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        use wl_clipboard_rs::paste::MimeType;
+        for mime_type in RTF_MIME_TYPES.iter().filter(|t| offered.is_none_or(|o| o.contains(**t))) {
+            match get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Specific(mime_type)) {
+                // RTF is 7-bit ASCII, but some writers leave stray 8-bit bytes or a trailing NUL in it
+                Ok((mut data, _)) => { use std::io::Read; let mut bytes = Vec::new(); data.read_to_end(&mut bytes)?; return Ok(String::from_utf8_lossy(&bytes).trim_end_matches('\0').to_string()); }
+                Err(e) => debug!("No {} content available: {}", mime_type, e),
+            }
+        }
+        Err("no RTF content available".into())
+    }
 
-    fn set_text_content(&self, text: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let opts = Options::new();
-        let source = MimeSource { source: Source::Bytes(text.as_bytes().to_vec().into_boxed_slice()), mime_type: CopyMimeType::Text };
-        wl_clipboard_rs::copy::copy_multi(opts, vec![source])?;
-        debug!("Successfully set text content: {} chars", text.len());
-        Ok(())
+    // Offered types listed in `formats.mime_types`, except the ones read into dedicated fields
+    fn get_extra_formats(&self, offered: &HashSet<String>) -> BTreeMap<String, String> {
+        use base64::Engine;
+        use std::io::Read;
+        use wl_clipboard_rs::paste::MimeType;
+        let mut extra = BTreeMap::new();
+        for mime_type in offered.iter().filter(|t| self.formats.allows_mime(t) && !is_builtin_mime_type(t)) {
+            let mut bytes = Vec::new();
+            let result = get_contents(ClipboardType::Regular, Seat::Unspecified, MimeType::Specific(mime_type))
+                .map_err(|e| e.to_string())
+                .and_then(|(data, _)| data.take(self.formats.max_mime_bytes as u64 + 1).read_to_end(&mut bytes).map_err(|e| e.to_string()));
+            match result {
+                Ok(_) if bytes.len() > self.formats.max_mime_bytes => debug!("{} is larger than formats.max_mime_bytes, leaving it out", mime_type),
+                Ok(_) => { extra.insert(mime_type.clone(), base64::engine::general_purpose::STANDARD.encode(&bytes)); }
+                Err(e) => debug!("Failed to read {}: {}", mime_type, e),
+            }
+        }
+        extra
     }
-    fn set_multi_format_content(&self, plain_text: &str, html: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let opts = Options::new();
-        let sources = vec![
-            MimeSource { source: Source::Bytes(plain_text.as_bytes().to_vec().into_boxed_slice()), mime_type: CopyMimeType::Text },
-            MimeSource { source: Source::Bytes(html.as_bytes().to_vec().into_boxed_slice()), mime_type: CopyMimeType::Specific("text/html".to_string()) },
-        ];
-        copy_multi(opts, sources)?; Ok(())
+
+    // Offer plain text plus whichever of HTML, RTF and extra MIME types `data` carries, in one selection
+    fn set_multi_format_content(&self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let bytes = |s: &str| Source::Bytes(s.as_bytes().to_vec().into_boxed_slice());
+        let mut sources = vec![MimeSource { source: bytes(&data.content), mime_type: CopyMimeType::Text }];
+        if let Some(ref html) = data.html {
+            sources.push(MimeSource { source: bytes(html), mime_type: CopyMimeType::Specific("text/html".to_string()) });
+        }
+        if let Some(ref rtf) = data.rtf {
+            for mime_type in RTF_MIME_TYPES {
+                sources.push(MimeSource { source: bytes(rtf), mime_type: CopyMimeType::Specific(mime_type.to_string()) });
+            }
+        }
+        for (mime_type, payload) in &data.extra_formats {
+            use base64::Engine;
+            if is_builtin_mime_type(mime_type) { continue; }
+            match base64::engine::general_purpose::STANDARD.decode(payload) {
+                Ok(bytes) => sources.push(MimeSource { source: Source::Bytes(bytes.into_boxed_slice()), mime_type: CopyMimeType::Specific(mime_type.clone()) }),
+                Err(e) => debug!("Skipping {}, its payload is not base64: {}", mime_type, e),
+            }
+        }
+        copy_multi(Options::new(), sources)?;
+        debug!("Set clipboard content: {} chars, html: {}, rtf: {}, {} other types", data.content.len(), data.html.is_some(), data.rtf.is_some(), data.extra_formats.len());
+        Ok(())
     }
 }
 
 impl ClipboardBackend for LinuxClipboardManager {
     fn get_clipboard_data(&self) -> Result<ClipboardData, Box<dyn std::error::Error + Send + Sync>> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-        let plain_text = self.get_text_content()?;
-        let html_content = self.get_html_content().ok();
-        let rtf_content = self.get_rtf_content().ok();
+        let plain_text = self.get_text_content();
+        // Only ask for formats that are offered; each request is a round trip to the compositor
+        let offered = get_mime_types(ClipboardType::Regular, Seat::Unspecified).ok();
+        if let Err(e) = &plain_text {
+            if offered.as_ref().is_none_or(HashSet::is_empty) { return Err(e.to_string().into()); }
+        }
+        let html_content = if offered.as_ref().is_none_or(|o| o.contains("text/html")) { self.get_html_content().ok() } else { None };
+        let rtf_content = self.get_rtf_content(offered.as_ref()).ok();
+        let extra_formats = match &offered {
+            Some(offered) if offered.iter().any(|t| self.formats.allows_mime(t) && !is_builtin_mime_type(t)) => {
+                use std::hash::{Hash, Hasher};
+                let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                let mut types: Vec<&String> = offered.iter().collect();
+                types.sort();
+                (types, plain_text.as_ref().ok(), &html_content, &rtf_content).hash(&mut hasher);
+                self.get_extra_formats_cached(offered, hasher.finish())
+            }
+            _ => BTreeMap::new(),
+        };
+        // Copies without plain text (an SVG from Inkscape) are still synced when a passthrough type matched
+        let plain_text = match plain_text {
+            Ok(text) => text,
+            Err(e) if html_content.is_none() && rtf_content.is_none() && extra_formats.is_empty() => return Err(e),
+            Err(_) => String::new(),
+        };
         let content_type = if html_content.is_some() { if rtf_content.is_some() { "mixed" } else { "html" } } else if rtf_content.is_some() { "rtf" } else { "text" }.to_string();
-        Ok(ClipboardData { content: plain_text, html: html_content, rtf: rtf_content, image: None, content_type, timestamp })
+        Ok(ClipboardData { content: plain_text, html: html_content, rtf: rtf_content, image: None, content_type, timestamp, seq: None, ttl: None, source_app: None, extra_formats, fingerprint: None })
     }
 
-    fn has_content_changed(&mut self, data: &ClipboardData, from_server: bool, server_timestamp: Option<u64>) -> bool {
-        let current_hash = Self::calculate_content_hash(data);
-        if from_server { if let Some(ts) = server_timestamp { self.last_server_timestamp = Some(ts); } }
-        if let Some(last) = self.last_content_hash { if last == current_hash { return false; } }
-        if !from_server { if let Some(server_ts) = self.last_server_timestamp { if data.timestamp <= server_ts + 5 { return false; } }}
-        if !from_server { self.last_content_hash = Some(current_hash); }
-        true
+    fn set_clipboard_data(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.extra_formats_cache.take();
+        self.set_multi_format_content(data)
     }
 
-    fn mark_content_as_sent(&mut self, data: &ClipboardData) { self.last_sent_hash = Some(Self::calculate_content_hash(data)); }
+    // Images are neither written nor read here
+    fn readable(&self, data: &ClipboardData) -> ClipboardData {
+        ClipboardData { image: None, ..data.clone() }
+    }
 
-    fn is_own_content_returned(&self, data: &ClipboardData) -> bool {
-        self.last_sent_hash.map_or(false, |h| h == Self::calculate_content_hash(data))
+    fn is_concealed(&self) -> bool {
+        match get_mime_types(ClipboardType::Regular, Seat::Unspecified) {
+            Ok(types) => CONCEALED_MIME_TYPES.iter().any(|t| types.contains(*t)),
+            Err(e) => { debug!("Failed to list offered MIME types: {}", e); false }
+        }
     }
 
-    fn set_clipboard_data_from_server(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        let result = if let Some(ref html) = data.html { self.set_multi_format_content(&data.content, html) } else { self.set_text_content(&data.content) };
-        if result.is_ok() {
-            self.last_content_hash = Some(Self::calculate_content_hash(data));
-            self.last_server_timestamp = Some(data.timestamp);
+    fn source_app(&self) -> Option<String> {
+        match x11_selection_owner() {
+            Ok(app) => app,
+            Err(e) => { debug!("Failed to look up the clipboard owner: {}", e); None }
         }
-        result
+    }
+
+    fn configure(&mut self, formats: &Formats) {
+        self.formats = formats.clone();
+        self.extra_formats_cache.take();
+    }
+
+    fn clear(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        use wl_clipboard_rs::copy::{clear, ClipboardType as CopyClipboardType, Seat as CopySeat};
+        clear(CopyClipboardType::Regular, CopySeat::All)?;
+        Ok(())
     }
 }
 
+
+// WM_CLASS of the X11 window owning CLIPBOARD, or the name of its process.
+// Wayland's data-control protocol does not say who offered a selection, so this only
+// works for X11 sessions and for XWayland apps (it is None for native Wayland apps,
+// whose selections XWayland's own window owns).
+fn x11_selection_owner() -> Result<Option<String>, Box<dyn std::error::Error + Send + Sync>> {
+    use x11rb::protocol::xproto::{AtomEnum, ConnectionExt};
+
+    if std::env::var_os("DISPLAY").is_none() { return Ok(None); }
+    let (conn, _) = x11rb::connect(None)?;
+    let atom = |name: &[u8]| -> Result<u32, Box<dyn std::error::Error + Send + Sync>> { Ok(conn.intern_atom(false, name)?.reply()?.atom) };
+    let owner = conn.get_selection_owner(atom(b"CLIPBOARD")?)?.reply()?.owner;
+    let net_wm_pid = atom(b"_NET_WM_PID")?;
+
+    // Toolkits often own the selection with a hidden child window, so walk up to a window that is labelled
+    let mut window = owner;
+    while window != x11rb::NONE {
+        // WM_CLASS is "instance\0class\0"; the class ("firefox", "KeePassXC") is what users recognise
+        let class = conn.get_property(false, window, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 256)?.reply()?;
+        let names: Vec<&[u8]> = class.value.split(|&b| b == 0).filter(|s| !s.is_empty()).collect();
+        if let Some(name) = names.get(1).or(names.first()) {
+            return Ok(Some(String::from_utf8_lossy(name).into_owned()));
+        }
+        let pid = conn.get_property(false, window, net_wm_pid, AtomEnum::CARDINAL, 0, 1)?.reply()?;
+        if let Some(pid) = pid.value32().and_then(|mut v| v.next()) {
+            if let Ok(comm) = std::fs::read_to_string(format!("/proc/{}/comm", pid)) {
+                return Ok(Some(comm.trim().to_string()));
+            }
+        }
+        let tree = conn.query_tree(window)?.reply()?;
+        if tree.parent == tree.root { break; }
+        window = tree.parent;
+    }
+    Ok(None)
+}
+
+// Types read into `content`, `html` and `rtf`, and X11 target atoms, which are never passed through
+fn is_builtin_mime_type(mime_type: &str) -> bool {
+    !mime_type.contains('/') || mime_type.starts_with("text/plain") || mime_type == "text/html" || RTF_MIME_TYPES.contains(&mime_type)
+}
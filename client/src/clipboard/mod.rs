@@ -1,13 +1,23 @@
 use crate::ClipboardData;
 
+// Platform clipboard I/O only; what counts as a change and what to send is decided by `SyncEngine`.
 pub trait ClipboardBackend {
     fn get_clipboard_data(&self) -> Result<ClipboardData, Box<dyn std::error::Error + Send + Sync>>;
-    fn has_content_changed(&mut self, data: &ClipboardData, from_server: bool, server_timestamp: Option<u64>) -> bool;
-    fn mark_content_as_sent(&mut self, data: &ClipboardData);
-    fn is_own_content_returned(&self, data: &ClipboardData) -> bool;
-    fn set_clipboard_data_from_server(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    fn set_clipboard_data(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+    // What reading `data` back after setting it returns, for formats the platform cannot write or read
+    fn readable(&self, data: &ClipboardData) -> ClipboardData;
+    // Whether the current owner marked the content as secret (password managers do)
+    fn is_concealed(&self) -> bool;
+    // Name of the application that owns the clipboard, where the platform exposes it
+    fn source_app(&self) -> Option<String>;
+    // Apply the `[formats]` settings that decide which extra MIME types are read
+    fn configure(&mut self, formats: &crate::config::Formats);
+    fn clear(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
 
+mod engine;
+pub use engine::SyncEngine;
+
 #[cfg(target_os = "linux")]
 mod linux;
 #[cfg(target_os = "windows")]
@@ -17,4 +27,3 @@ mod windows;
 pub use linux::LinuxClipboardManager as ClipboardManager;
 #[cfg(target_os = "windows")]
 pub use windows::WindowsClipboardManager as ClipboardManager;
-
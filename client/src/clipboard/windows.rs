@@ -4,24 +4,19 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use tracing::debug;
 use clipboard_win::{formats, get_clipboard, set_clipboard};
 
-pub struct WindowsClipboardManager {
-    last_content_hash: Option<u64>,
-    last_server_timestamp: Option<u64>,
-    last_sent_hash: Option<u64>,
-}
+// Registered formats password managers add to a copied secret
+const CONCEALED_FORMATS: &[&str] = &[
+    "ExcludeClipboardContentFromMonitorProcessing",
+    "Clipboard Viewer Ignore",
+];
+// DWORD that keeps a copy out of the clipboard history when 0; apps also set it to 1 to allow it
+const HISTORY_FORMAT: &str = "CanIncludeInClipboardHistory";
+
+pub struct WindowsClipboardManager;
 
 impl WindowsClipboardManager {
     pub fn new() -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        Ok(Self { last_content_hash: None, last_server_timestamp: None, last_sent_hash: None })
-    }
-    fn calculate_content_hash(data: &ClipboardData) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
-        let mut hasher = DefaultHasher::new();
-        data.content.hash(&mut hasher);
-        if let Some(ref html) = data.html { html.hash(&mut hasher); }
-        if let Some(ref rtf) = data.rtf { rtf.hash(&mut hasher); }
-        hasher.finish()
+        Ok(Self)
     }
 }
 
@@ -29,24 +24,57 @@ impl ClipboardBackend for WindowsClipboardManager {
     fn get_clipboard_data(&self) -> Result<ClipboardData, Box<dyn std::error::Error + Send + Sync>> {
         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
         let plain_text = match get_clipboard(formats::Unicode) { Ok(t) => t, Err(e) => return Err(format!("get clipboard: {}", e).into()) };
-        Ok(ClipboardData { content: plain_text, html: None, rtf: None, image: None, content_type: "text".to_string(), timestamp })
+        Ok(ClipboardData { content: plain_text, html: None, rtf: None, image: None, content_type: "text".to_string(), timestamp, seq: None, ttl: None, source_app: None, extra_formats: Default::default(), fingerprint: None })
     }
-    fn has_content_changed(&mut self, data: &ClipboardData, from_server: bool, server_timestamp: Option<u64>) -> bool {
-        let current_hash = Self::calculate_content_hash(data);
-        if from_server { if let Some(ts) = server_timestamp { self.last_server_timestamp = Some(ts); } }
-        if let Some(last) = self.last_content_hash { if last == current_hash { return false; } }
-        if !from_server { if let Some(server_ts) = self.last_server_timestamp { if data.timestamp <= server_ts + 5 { return false; } }}
-        if !from_server { self.last_content_hash = Some(current_hash); }
-        true
-    }
-    fn mark_content_as_sent(&mut self, data: &ClipboardData) { self.last_sent_hash = Some(Self::calculate_content_hash(data)); }
-    fn is_own_content_returned(&self, data: &ClipboardData) -> bool { self.last_sent_hash.map_or(false, |h| h == Self::calculate_content_hash(data)) }
-    fn set_clipboard_data_from_server(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    fn set_clipboard_data(&mut self, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         set_clipboard(formats::Unicode, &data.content).map_err(|e| format!("set clipboard: {}", e))?;
-        self.last_content_hash = Some(Self::calculate_content_hash(data));
-        self.last_server_timestamp = Some(data.timestamp);
         debug!("Set text content on Windows: {} chars", data.content.len());
         Ok(())
     }
+    // Only plain text is written and read on Windows
+    fn readable(&self, data: &ClipboardData) -> ClipboardData {
+        ClipboardData { html: None, rtf: None, image: None, extra_formats: Default::default(), ..data.clone() }
+    }
+    fn is_concealed(&self) -> bool {
+        use clipboard_win::raw::{is_format_avail, register_format};
+        let available = |name: &str| register_format(name).map(|format| format.get()).filter(|format| is_format_avail(*format));
+        if CONCEALED_FORMATS.iter().any(|name| available(name).is_some()) { return true; }
+        let Some(format) = available(HISTORY_FORMAT) else { return false };
+        match get_clipboard::<Vec<u8>, _>(formats::RawData(format)) {
+            Ok(value) => value.get(..4).and_then(|dword| dword.try_into().ok()).map(u32::from_le_bytes) == Some(0),
+            Err(e) => { debug!("Failed to read {}: {}", HISTORY_FORMAT, e); false }
+        }
+    }
+    fn source_app(&self) -> Option<String> {
+        use windows_sys::Win32::Foundation::CloseHandle;
+        use windows_sys::Win32::System::DataExchange::GetClipboardOwner;
+        use windows_sys::Win32::System::Threading::{OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION};
+        use windows_sys::Win32::UI::WindowsAndMessaging::GetWindowThreadProcessId;
+
+        // The owner window's process, e.g. "chrome" or "KeePassXC"; None when nobody owns the clipboard
+        unsafe {
+            let owner = GetClipboardOwner();
+            if owner == 0 { return None; }
+            let mut pid = 0u32;
+            GetWindowThreadProcessId(owner, &mut pid);
+            if pid == 0 { return None; }
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+            if process == 0 { debug!("Failed to open clipboard owner process {}", pid); return None; }
+            let mut buf = [0u16; 1024];
+            let mut len = buf.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, PROCESS_NAME_WIN32, buf.as_mut_ptr(), &mut len);
+            CloseHandle(process);
+            if ok == 0 { return None; }
+            let path = String::from_utf16_lossy(&buf[..len as usize]);
+            std::path::Path::new(&path).file_stem().map(|s| s.to_string_lossy().into_owned())
+        }
+    }
+    // Only plain text is read and written on Windows, so extra MIME types never apply
+    fn configure(&mut self, _formats: &crate::config::Formats) {}
+    fn clear(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let _clipboard = clipboard_win::Clipboard::new_attempts(10).map_err(|e| format!("open clipboard: {}", e))?;
+        clipboard_win::raw::empty().map_err(|e| format!("clear clipboard: {}", e))?;
+        Ok(())
+    }
 }
 
@@ -1,29 +1,675 @@
+// Client configuration stored in `<config dir>/shared-clipboard/config.toml`.
+//
+// Files written by older versions (a single `server_url=` line) are migrated on
+// load; keys this version does not know about are kept and written back on save.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::ClipboardData;
+
+// Bump together with a new step in `migrate`.
+pub const CURRENT_VERSION: i64 = 1;
+
+pub const DEFAULT_PROFILE: &str = "default";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub version: i64,
+    // Key into `profiles` of the server currently synced with
+    pub active_profile: String,
+    // Identifies this machine to the server, sent as `X-Clipboard-Device`
+    pub device_name: String,
+    // Whether this machine sends local changes, applies remote ones, or both
+    pub direction: Direction,
+    pub poll_interval_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+    pub formats: Formats,
+    pub html: Html,
+    pub limits: Limits,
+    pub filters: Filters,
+    pub apps: Apps,
+    pub sensitive: Sensitive,
+    #[serde(skip_serializing_if = "PipelineConfig::is_empty")]
+    pub pipeline: PipelineConfig,
+    // Action name → key combination, e.g. `pause = "Ctrl+Alt+P"`; parsed and validated only for now
+    pub hotkeys: BTreeMap<String, String>,
+    pub profiles: BTreeMap<String, Profile>,
+    // Keys unknown to this version, preserved on save
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+// A named server to sync with, e.g. `[profiles.work]`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub server_url: String,
+    // Sent as `Authorization: Bearer <token>` (e.g. for an authenticating reverse proxy)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_token: Option<String>,
+    // Clipboard channel on the server, sent as `X-Clipboard-Channel`; the server's `default` channel when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    // Stay connected (and receive updates) even when this is not the active profile
+    pub connect: bool,
+    // Which local clipboard changes are sent to this profile
+    pub send: SendMode,
+    #[serde(flatten)]
+    pub extra: toml::Table,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SendMode {
+    // Every local change
+    #[default]
+    All,
+    // Only through an explicit "Send to" action
+    Manual,
+    // Receive only
+    None,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Both,
+    // Presenter laptops and similar: local changes go out, remote ones are ignored
+    Send,
+    // Kiosks, build agents: remote changes are applied, local ones stay local
+    Receive,
+}
+
+impl Direction {
+    #[cfg(any(feature = "tray", feature = "gui"))]
+    pub const ALL: [Direction; 3] = [Direction::Both, Direction::Send, Direction::Receive];
+
+    pub fn sends(self) -> bool {
+        self != Direction::Receive
+    }
+
+    pub fn receives(self) -> bool {
+        self != Direction::Send
+    }
+
+    // Menu and settings label
+    #[cfg(any(feature = "tray", feature = "gui"))]
+    pub fn label(self) -> &'static str {
+        match self {
+            Direction::Both => "Send and receive",
+            Direction::Send => "Send only",
+            Direction::Receive => "Receive only",
+        }
+    }
+}
+
+// Clipboard representations that are synced; disabled ones are dropped in both directions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Formats {
+    pub text: bool,
+    pub html: bool,
+    pub rtf: bool,
+    pub image: bool,
+    // Other MIME types carried verbatim, e.g. "image/svg+xml" or "application/x-vnd.oasis.*";
+    // empty disables passthrough. Only Linux clients read and offer them.
+    pub mime_types: Vec<String>,
+    // Payloads of a passthrough type larger than this are left out
+    pub max_mime_bytes: usize,
+}
+
+// Sanitising of HTML from other devices before it reaches the local clipboard. Scripts, event
+// handlers and `javascript:` links are always removed when enabled.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Html {
+    pub sanitize: bool,
+    // Tags that are kept; empty keeps the built-in allowlist of formatting tags
+    pub tags: Vec<String>,
+    // Attributes kept on every tag; empty keeps the built-in `lang` and `title`
+    pub attributes: Vec<String>,
+    // Keep `<img>` sources on http(s) URLs; off by default so tracking pixels never load
+    pub remote_images: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    // Updates larger than this (all formats together) are not synced
+    pub max_content_bytes: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filters {
+    // Regular expressions; local content matching any of them is not sent
+    pub ignore_patterns: Vec<String>,
+}
+
+// Extra filter/transform stages, run in order after the built-in ones (see pipeline.rs), e.g.
+//   [[pipeline.outgoing]]
+//   type = "replace"
+//   pattern = "https://intranet\\.corp/"
+//   replacement = "https://vpn.example.com/"
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelineConfig {
+    // Applied to local changes before they are sent
+    pub outgoing: Vec<Stage>,
+    // Applied to updates from a server before they reach the local clipboard
+    pub incoming: Vec<Stage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Stage {
+    // Strip whitespace at the end of every line and trailing blank lines
+    TrimWhitespace,
+    // Drop HTML, RTF and images; content without plain text is not synced
+    PlainTextOnly,
+    // Drop content larger than this, all formats together
+    MaxSize { bytes: usize },
+    // Drop text matching the regular expression
+    Ignore { pattern: String },
+    // Replace every match in the text, HTML and RTF; `$1` refers to capture groups
+    Replace { pattern: String, replacement: String },
+}
+
+// Which applications' copies are sent, matched case-insensitively against the clipboard owner's
+// name: its X11 WM_CLASS or process name on Linux, its executable name on Windows.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Apps {
+    // Copies from these applications are never sent
+    pub exclude: Vec<String>,
+    // When non-empty, only copies from these applications are sent
+    pub include: Vec<String>,
+    // Whether copies from an unknown source (native Wayland apps) are sent despite `include`
+    pub allow_unknown: bool,
+}
+
+// Detection of passwords and secrets in local copies, which are then not synced automatically.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Sensitive {
+    pub action: SensitiveAction,
+    // Respect "concealed" markers set by password managers
+    pub honor_hints: bool,
+    // Built-in detectors for well-known key and token formats
+    pub builtin_patterns: bool,
+    // Additional regular expressions that mark content as sensitive
+    pub patterns: Vec<String>,
+    // Single words at least this long with at least `min_entropy` bits per character
+    // look like generated keys; 0 disables the check
+    pub min_entropy_length: usize,
+    pub min_entropy: f64,
+    // Seconds the server keeps sensitive content that is sent anyway (confirm/allow)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ttl: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SensitiveAction {
+    // Never sent
+    #[default]
+    Skip,
+    // Held until sent explicitly from the tray or with `clipboard-client send-held`
+    Confirm,
+    // Detection only logs a notice
+    Allow,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            active_profile: DEFAULT_PROFILE.to_string(),
+            device_name: default_device_name(),
+            direction: Direction::Both,
+            poll_interval_ms: 100,
+            log_file: None,
+            formats: Formats::default(),
+            html: Html::default(),
+            limits: Limits::default(),
+            filters: Filters::default(),
+            apps: Apps::default(),
+            sensitive: Sensitive::default(),
+            pipeline: PipelineConfig::default(),
+            hotkeys: BTreeMap::new(),
+            profiles: BTreeMap::from([(DEFAULT_PROFILE.to_string(), Profile::default())]),
+            extra: toml::Table::new(),
+        }
+    }
+}
+
+impl Profile {
+    // Value of the Authorization header, if a token is configured.
+    pub fn authorization(&self) -> Option<String> {
+        self.auth_token.as_ref().map(|t| format!("Bearer {}", t.trim()))
+    }
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            server_url: std::env::var("CLIPBOARD_SERVER_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string()),
+            auth_token: None,
+            channel: None,
+            connect: false,
+            send: SendMode::All,
+            extra: toml::Table::new(),
+        }
+    }
+}
+
+impl Default for Sensitive {
+    fn default() -> Self {
+        Self {
+            action: SensitiveAction::Skip,
+            honor_hints: true,
+            builtin_patterns: true,
+            patterns: Vec::new(),
+            min_entropy_length: 24,
+            min_entropy: 4.3,
+            ttl: None,
+        }
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Self {
+        Self { text: true, html: true, rtf: true, image: true, mime_types: Vec::new(), max_mime_bytes: 1024 * 1024 }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self { max_content_bytes: 10 * 1024 * 1024 }
+    }
+}
+
+fn default_device_name() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl Formats {
+    // Whether `mime` is listed in `mime_types`; a trailing `*` matches any suffix.
+    pub fn allows_mime(&self, mime: &str) -> bool {
+        self.mime_types.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => mime.to_ascii_lowercase().starts_with(&prefix.to_ascii_lowercase()),
+            None => pattern.eq_ignore_ascii_case(mime),
+        })
+    }
+
+    // Drop disabled representations. Returns false when nothing syncable remains.
+    pub fn apply(&self, data: &mut ClipboardData) -> bool {
+        if !self.html { data.html = None; }
+        if !self.rtf { data.rtf = None; }
+        if !self.image { data.image = None; }
+        data.extra_formats.retain(|mime, payload| self.allows_mime(mime) && payload.len() / 4 * 3 <= self.max_mime_bytes);
+        let rich = data.html.is_some() || data.rtf.is_some() || data.image.is_some() || !data.extra_formats.is_empty();
+        if !self.text && rich {
+            data.content.clear();
+        }
+        if !self.text && !rich {
+            return false;
+        }
+        data.update_content_type();
+        true
+    }
+}
+
+impl Limits {
+    pub fn allows(&self, data: &ClipboardData) -> bool {
+        let size = data.content.len()
+            + data.html.as_ref().map_or(0, String::len)
+            + data.rtf.as_ref().map_or(0, String::len)
+            + data.image.as_ref().map_or(0, String::len)
+            + data.extra_formats.values().map(String::len).sum::<usize>();
+        size <= self.max_content_bytes
+    }
+}
+
+impl Default for Html {
+    fn default() -> Self {
+        Self { sanitize: true, tags: Vec::new(), attributes: Vec::new(), remote_images: false }
+    }
+}
+
+impl Default for Apps {
+    fn default() -> Self {
+        Self { exclude: Vec::new(), include: Vec::new(), allow_unknown: true }
+    }
+}
+
+impl Apps {
+    // Whether content copied from `source` may be sent.
+    pub fn allows(&self, source: Option<&str>) -> bool {
+        let listed = |names: &[String], app: &str| names.iter().any(|n| n.eq_ignore_ascii_case(app));
+        match source {
+            Some(app) if listed(&self.exclude, app) => false,
+            Some(app) => self.include.is_empty() || listed(&self.include, app),
+            None => self.include.is_empty() || self.allow_unknown,
+        }
+    }
+}
+
+impl PipelineConfig {
+    pub fn is_empty(&self) -> bool {
+        self.outgoing.is_empty() && self.incoming.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    NoConfigDir,
+    Io { path: PathBuf, source: std::io::Error },
+    Parse { path: PathBuf, message: String },
+    Invalid { path: PathBuf, field: String, message: String },
+    UnsupportedVersion { path: PathBuf, version: i64 },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::NoConfigDir => write!(f, "could not determine the user configuration directory"),
+            ConfigError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ConfigError::Parse { path, message } => write!(f, "{}: {}", path.display(), message.trim_end()),
+            ConfigError::Invalid { path, field, message } => write!(f, "{}: invalid `{}`: {}", path.display(), field, message),
+            ConfigError::UnsupportedVersion { path, version } => write!(
+                f,
+                "{} has config version {}, but this client only understands up to {}; please upgrade",
+                path.display(), version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
 
-fn config_path() -> Option<PathBuf> {
+pub fn config_path() -> Option<PathBuf> {
     let dir = dirs::config_dir()?;
     let path = dir.join("shared-clipboard");
     let _ = fs::create_dir_all(&path);
     Some(path.join("config.toml"))
 }
 
-pub fn load_server_url() -> Option<String> {
-    let path = config_path()?;
-    let text = fs::read_to_string(path).ok()?;
-    for line in text.lines() {
-        if let Some(rest) = line.strip_prefix("server_url=") {
-            return Some(rest.trim_matches('"').to_string());
+// Load the config file, or defaults when it does not exist yet.
+pub fn load() -> Result<Config, ConfigError> {
+    let path = config_path().ok_or(ConfigError::NoConfigDir)?;
+    load_from(&path)
+}
+
+pub fn load_from(path: &Path) -> Result<Config, ConfigError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+        Err(source) => return Err(ConfigError::Io { path: path.to_path_buf(), source }),
+    };
+    parse(path, &text)
+}
+
+pub fn parse(path: &Path, text: &str) -> Result<Config, ConfigError> {
+    let mut table: toml::Table = match text.parse() {
+        Ok(table) => table,
+        // Pre-TOML files were written without escaping; fall back to the old line format
+        Err(e) => legacy_table(text).ok_or_else(|| ConfigError::Parse { path: path.to_path_buf(), message: e.to_string() })?,
+    };
+    migrate(path, &mut table)?;
+    let config: Config = toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| ConfigError::Parse { path: path.to_path_buf(), message: e.to_string() })?;
+    config.validate(path)?;
+    Ok(config)
+}
+
+fn legacy_table(text: &str) -> Option<toml::Table> {
+    let url = text.lines().find_map(|line| line.strip_prefix("server_url="))?;
+    let mut table = toml::Table::new();
+    table.insert("server_url".into(), toml::Value::String(url.trim().trim_matches('"').to_string()));
+    Some(table)
+}
+
+// Upgrade a raw config table to CURRENT_VERSION, one version at a time.
+fn migrate(path: &Path, table: &mut toml::Table) -> Result<(), ConfigError> {
+    let version = match table.get("version") {
+        None => 0,
+        Some(toml::Value::Integer(v)) => *v,
+        Some(_) => return Err(ConfigError::Invalid { path: path.to_path_buf(), field: "version".into(), message: "must be an integer".into() }),
+    };
+    if version > CURRENT_VERSION {
+        return Err(ConfigError::UnsupportedVersion { path: path.to_path_buf(), version });
+    }
+    // v0 → v1: only `server_url` existed; it becomes the "default" profile and everything else takes its default
+    if version < 1 {
+        if let Some(url) = table.remove("server_url") {
+            let profile = toml::Table::from_iter([("server_url".to_string(), url)]);
+            let profiles = toml::Table::from_iter([(DEFAULT_PROFILE.to_string(), toml::Value::Table(profile))]);
+            table.insert("profiles".into(), toml::Value::Table(profiles));
+            table.insert("active_profile".into(), toml::Value::String(DEFAULT_PROFILE.into()));
+        }
+    }
+    table.insert("version".into(), toml::Value::Integer(CURRENT_VERSION));
+    Ok(())
+}
+
+impl Config {
+    pub fn validate(&self, path: &Path) -> Result<(), ConfigError> {
+        let invalid = |field: &str, message: String| ConfigError::Invalid { path: path.to_path_buf(), field: field.into(), message };
+
+        if !self.profiles.contains_key(&self.active_profile) {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(invalid("active_profile", format!("no profile named '{}' (known: {})", self.active_profile, known.join(", "))));
+        }
+        for (name, profile) in &self.profiles {
+            let field = |key: &str| format!("profiles.{}.{}", name, key);
+            if name.trim().is_empty() {
+                return Err(invalid("profiles", "profile names must not be empty".into()));
+            }
+            match url::Url::parse(&profile.server_url) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+                Ok(url) => return Err(invalid(&field("server_url"), format!("unsupported scheme '{}', expected http or https", url.scheme()))),
+                Err(e) => return Err(invalid(&field("server_url"), format!("'{}' is not a URL: {}", profile.server_url, e))),
+            }
+            let single_line = |value: &Option<String>| value.as_deref().is_none_or(|v| !v.trim().is_empty() && !v.contains(['\r', '\n']));
+            for (key, value) in [("auth_token", &profile.auth_token), ("channel", &profile.channel)] {
+                if !single_line(value) {
+                    return Err(invalid(&field(key), "must be non-empty and on a single line".into()));
+                }
+            }
+            // Same rule as the server's channel names
+            let channel_name = |c: &str| c.len() <= 64 && c.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+            if let Some(channel) = profile.channel.as_deref().filter(|c| !channel_name(c)) {
+                return Err(invalid(&field("channel"), format!("'{}' may only contain letters, digits, '-', '_' and '.', at most 64 characters", channel)));
+            }
+        }
+        if self.device_name.is_empty() || !self.device_name.chars().all(|c| c.is_ascii_graphic() || c == ' ') {
+            return Err(invalid("device_name", format!("'{}' must be non-empty printable ASCII", self.device_name)));
+        }
+        if self.poll_interval_ms < 10 {
+            return Err(invalid("poll_interval_ms", format!("{} is too small, minimum is 10", self.poll_interval_ms)));
+        }
+        if self.limits.max_content_bytes == 0 {
+            return Err(invalid("limits.max_content_bytes", "must be greater than 0".into()));
         }
+        if let Some(mime) = self.formats.mime_types.iter().find(|m| !m.contains('/')) {
+            return Err(invalid("formats.mime_types", format!("'{}' is not a MIME type like \"image/svg+xml\"", mime)));
+        }
+        if self.formats.max_mime_bytes == 0 {
+            return Err(invalid("formats.max_mime_bytes", "must be greater than 0".into()));
+        }
+        if let Some(tag) = self.html.tags.iter().find(|t| ["script", "style"].contains(&t.to_ascii_lowercase().as_str())) {
+            return Err(invalid("html.tags", format!("'{}' cannot be allowed, its content is always removed", tag)));
+        }
+        if let Some(attr) = self.html.attributes.iter().find(|a| a.to_ascii_lowercase().starts_with("on")) {
+            return Err(invalid("html.attributes", format!("'{}' looks like an event handler, which is always removed", attr)));
+        }
+        for (key, names) in [("apps.exclude", &self.apps.exclude), ("apps.include", &self.apps.include)] {
+            if names.iter().any(|n| n.trim().is_empty()) {
+                return Err(invalid(key, "application names must not be empty".into()));
+            }
+        }
+        for stage in self.pipeline.outgoing.iter().chain(&self.pipeline.incoming) {
+            if let Stage::MaxSize { bytes: 0 } = stage {
+                return Err(invalid("pipeline", "max_size stages need bytes greater than 0".into()));
+            }
+        }
+        if let Err((field, message)) = crate::pipeline::Pipeline::outgoing(self).and(crate::pipeline::Pipeline::incoming(self)) {
+            return Err(invalid(&field, message));
+        }
+        if let Err(e) = crate::sensitive::Detector::new(&self.sensitive) {
+            return Err(invalid("sensitive.patterns", e.to_string()));
+        }
+        if !(0.0..=8.0).contains(&self.sensitive.min_entropy) {
+            return Err(invalid("sensitive.min_entropy", "must be between 0 and 8 bits per character".into()));
+        }
+        for (action, keys) in &self.hotkeys {
+            if keys.split('+').any(|k| k.trim().is_empty()) {
+                return Err(invalid(&format!("hotkeys.{}", action), format!("'{}' is not a key combination like \"Ctrl+Alt+V\"", keys)));
+            }
+        }
+        Ok(())
     }
-    None
+
+    // The profile being synced with. Validation guarantees it exists.
+    pub fn active(&self) -> &Profile {
+        &self.profiles[&self.active_profile]
+    }
+
+    pub fn active_mut(&mut self) -> &mut Profile {
+        self.profiles.entry(self.active_profile.clone()).or_default()
+    }
+
+    // Profiles the client stays connected to: the active one and those with `connect = true`.
+    pub fn joined(&self) -> impl Iterator<Item = (&String, &Profile)> {
+        self.profiles.iter().filter(|(name, profile)| **name == self.active_profile || profile.connect)
+    }
+
+}
+
+#[cfg(any(feature = "tray", feature = "gui"))]
+pub fn save(config: &Config) -> Result<(), ConfigError> {
+    let path = config_path().ok_or(ConfigError::NoConfigDir)?;
+    let text = toml::to_string_pretty(config)
+        .map_err(|e| ConfigError::Parse { path: path.clone(), message: e.to_string() })?;
+    // Write via a temporary file so a crash never leaves a half-written config
+    let tmp = path.with_extension("toml.tmp");
+    fs::write(&tmp, text).map_err(|source| ConfigError::Io { path: tmp.clone(), source })?;
+    fs::rename(&tmp, &path).map_err(|source| ConfigError::Io { path: path.clone(), source })
 }
 
-pub fn save_server_url(url: &str) -> std::io::Result<()> {
-    if let Some(path) = config_path() {
-        let content = format!("server_url=\"{}\"\n", url);
-        fs::write(path, content)?;
+// Re-read the file, apply `change` and save, so edits made by hand in the meantime are kept.
+// The file is left untouched (comments included) when the change is a no-op.
+#[cfg(any(feature = "tray", feature = "gui"))]
+pub fn update(change: impl FnOnce(&mut Config)) -> Result<Config, ConfigError> {
+    let mut config = load()?;
+    let before = config.clone();
+    change(&mut config);
+    if config != before {
+        save(&config)?;
     }
-    Ok(())
+    Ok(config)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path() -> PathBuf {
+        PathBuf::from("config.toml")
+    }
+
+    // What `save` writes, read back the way `load` does
+    fn save_and_load(config: &Config) -> Config {
+        parse(&path(), &toml::to_string_pretty(config).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn v0_line_format_becomes_the_default_profile() {
+        // As saved (quoted, which is also valid TOML) and as edited by hand
+        for text in ["server_url=\"http://10.0.0.2:8080\"\n", "server_url=http://10.0.0.2:8080\n"] {
+            let config = parse(&path(), text).unwrap();
+            assert_eq!(config.version, CURRENT_VERSION);
+            assert_eq!(config.active_profile, DEFAULT_PROFILE);
+            assert_eq!(config.active().server_url, "http://10.0.0.2:8080");
+            assert_eq!(save_and_load(&config), config);
+        }
+    }
+
+    #[test]
+    fn v1_round_trips() {
+        let text = r#"
+            version = 1
+            active_profile = "home"
+            device_name = "laptop"
+            direction = "send"
+
+            [profiles.home]
+            server_url = "http://192.168.1.10:8080"
+
+            [profiles.work]
+            server_url = "https://clip.example.com"
+            auth_token = "secret"
+            channel = "team"
+            connect = true
+            send = "manual"
+        "#;
+        let config = parse(&path(), text).unwrap();
+        assert_eq!(config.direction, Direction::Send);
+        assert_eq!(config.profiles["work"].channel.as_deref(), Some("team"));
+        assert_eq!(config.profiles["work"].send, SendMode::Manual);
+        assert_eq!(save_and_load(&config), config);
+    }
+
+    #[test]
+    fn unknown_keys_survive_a_save() {
+        let text = r#"
+            version = 1
+            from_a_newer_client = 7
+
+            [newer_table]
+            key = "value"
+
+            [profiles.default]
+            server_url = "http://127.0.0.1:8080"
+            newer_profile_key = true
+        "#;
+        let mut config = parse(&path(), text).unwrap();
+        config.direction = Direction::Receive;
+        let saved = save_and_load(&config);
+        assert_eq!(saved.extra["from_a_newer_client"].as_integer(), Some(7));
+        assert_eq!(saved.extra["newer_table"]["key"].as_str(), Some("value"));
+        assert_eq!(saved.active().extra["newer_profile_key"].as_bool(), Some(true));
+        assert_eq!(saved, config);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let text = format!("version = {}\n", CURRENT_VERSION + 1);
+        assert!(matches!(
+            parse(&path(), &text),
+            Err(ConfigError::UnsupportedVersion { version, .. }) if version == CURRENT_VERSION + 1
+        ));
+    }
+}
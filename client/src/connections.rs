@@ -0,0 +1,216 @@
+// Managed WebSocket connections, one per joined profile.
+// The active profile is always joined; other profiles join with `connect = true`.
+// Every connection applies incoming updates to the local clipboard, and clears it
+// again when the server clears or expires an entry it still holds; which local changes are
+// sent where is decided by each profile's `send` mode (see main.rs).
+
+use clipboard_common::Fingerprint;
+use futures_util::StreamExt;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{error, info};
+
+use crate::clipboard::{ClipboardManager, SyncEngine};
+use crate::config::{Config, Profile};
+use crate::pipeline::Pipeline;
+use crate::{tray, ClipboardMessage, ServerNotice};
+
+pub type SharedClipboard = Arc<Mutex<SyncEngine<ClipboardManager>>>;
+
+// Shared state handed to every connection task.
+#[derive(Clone)]
+pub struct Context {
+    pub clipboard: SharedClipboard,
+    pub config_rx: tokio::sync::watch::Receiver<Config>,
+    pub paused: Arc<AtomicBool>,
+    pub tray: Option<Arc<dyn tray::Tray>>,
+}
+
+struct Connection {
+    // Settings the task was started with; any change restarts it
+    profile: Profile,
+    device_name: String,
+    connected: Arc<AtomicBool>,
+    task: JoinHandle<()>,
+}
+
+pub struct Connections {
+    ctx: Context,
+    active: HashMap<String, Connection>,
+}
+
+impl Connections {
+    pub fn new(ctx: Context) -> Self {
+        Self { ctx, active: HashMap::new() }
+    }
+
+    // Start, stop or restart connections so they match the joined profiles in `config`.
+    pub fn sync(&mut self, config: &Config) {
+        let wanted: HashMap<&String, &Profile> = config.joined().collect();
+
+        self.active.retain(|name, conn| {
+            let keep = wanted.get(name).is_some_and(|p| **p == conn.profile) && conn.device_name == config.device_name;
+            if !keep {
+                info!("Closing connection for profile '{}'", name);
+                conn.task.abort();
+            }
+            keep
+        });
+
+        for (name, profile) in wanted {
+            if self.active.contains_key(name) {
+                continue;
+            }
+            info!("Joining profile '{}' at {}", name, profile.server_url);
+            let connected = Arc::new(AtomicBool::new(false));
+            let task = tokio::spawn(run_connection(name.clone(), profile.clone(), connected.clone(), self.ctx.clone()));
+            self.active.insert(name.clone(), Connection {
+                profile: profile.clone(),
+                device_name: config.device_name.clone(),
+                connected,
+                task,
+            });
+        }
+
+        // The tray shows the state of the active profile's connection
+        if let Some(tray) = &self.ctx.tray {
+            let connected = self.active.get(&config.active_profile).is_some_and(|c| c.connected.load(Ordering::Relaxed));
+            tray.set_connected(connected);
+        }
+    }
+}
+
+impl Drop for Connections {
+    fn drop(&mut self) {
+        for conn in self.active.values() {
+            conn.task.abort();
+        }
+    }
+}
+
+// Keep one profile connected, reconnecting with exponential backoff.
+async fn run_connection(name: String, profile: Profile, connected: Arc<AtomicBool>, ctx: Context) {
+    let mut reconnect_delay = Duration::from_secs(1);
+    const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+    loop {
+        let result = connect_once(&name, &profile, &connected, &ctx).await;
+        set_connected(&name, &connected, &ctx, false);
+        match result {
+            Ok(Some(delay)) => {
+                info!("[{}] Server asked to reconnect in {:?}", name, delay);
+                reconnect_delay = delay;
+            }
+            Ok(None) => {
+                info!("[{}] Connection ended normally, attempting reconnect...", name);
+                reconnect_delay = Duration::from_secs(1); // Reset delay on successful connection
+            }
+            Err(e) => {
+                error!("[{}] Connection failed: {}, retrying in {:?}...", name, e, reconnect_delay);
+            }
+        }
+
+        tokio::time::sleep(reconnect_delay).await;
+
+        // Exponential backoff with maximum delay
+        reconnect_delay = std::cmp::min(reconnect_delay * 2, MAX_RECONNECT_DELAY);
+
+        info!("[{}] Attempting to reconnect to {}", name, profile.server_url);
+    }
+}
+
+fn set_connected(name: &str, connected: &AtomicBool, ctx: &Context, value: bool) {
+    connected.store(value, Ordering::Relaxed);
+    if let Some(tray) = &ctx.tray {
+        if ctx.config_rx.borrow().active_profile == name {
+            tray.set_connected(value);
+        }
+    }
+}
+
+// Clear the local clipboard if it still holds `local`, the content a cleared entry was applied
+// as or copied here as before it was sent. Anything copied since is left alone.
+fn clear_if_holding(name: &str, local: Fingerprint, ctx: &Context) {
+    let mut clipboard = ctx.clipboard.lock().unwrap();
+    if !clipboard.holds(local) { return; }
+    match clipboard.clear() {
+        Ok(()) => info!("[{}] Clipboard entry cleared on the server, cleared the local clipboard", name),
+        Err(e) => error!("[{}] Failed to clear the local clipboard: {}", name, e),
+    }
+}
+
+// Returns the reconnect delay requested by the server, if it announced a shutdown.
+async fn connect_once(
+    name: &str,
+    profile: &Profile,
+    connected: &AtomicBool,
+    ctx: &Context,
+) -> Result<Option<Duration>, Box<dyn std::error::Error + Send + Sync>> {
+    let request = crate::websocket_request(&profile.server_url, &ctx.config_rx.borrow(), profile)?;
+    let (ws_stream, _) = connect_async(request).await?;
+    info!("[{}] Connected to WebSocket server", name);
+    set_connected(name, connected, ctx, true);
+
+    let (_ws_sender, mut ws_receiver) = ws_stream.split();
+    // Latest entry from this server that reached the local clipboard, by sequence number, with
+    // what the clipboard holds for it, to recognise it in a later `clipboard_clear`
+    let mut holding: Option<(u64, Fingerprint)> = None;
+    // Validated on load; rebuilt whenever the config is reloaded
+    let mut config_rx = ctx.config_rx.clone();
+    let mut incoming = Pipeline::incoming(&config_rx.borrow_and_update()).map_err(|(field, message)| format!("{}: {}", field, message))?;
+    while let Some(msg) = ws_receiver.next().await {
+        let text = match msg {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) => { info!("[{}] WebSocket connection closed by server", name); break; }
+            Err(e) => { error!("[{}] WebSocket error: {}", name, e); break; }
+            _ => continue,
+        };
+        if let Ok(mut clipboard_msg) = serde_json::from_str::<ClipboardMessage>(&text) {
+            if clipboard_msg.msg_type != "clipboard_update" { continue; }
+            // Our own content returned from server: the clipboard still holds the copy it was sent from
+            if let Some(local) = ctx.clipboard.lock().unwrap().local_copy_of(&clipboard_msg.data) {
+                info!("[{}] Own content returned from server, ignoring", name);
+                holding = clipboard_msg.data.seq.map(|seq| (seq, local));
+                continue;
+            }
+            if ctx.paused.load(Ordering::Relaxed) { info!("[{}] Sync paused, ignoring update from server", name); continue; }
+            info!("[{}] Received clipboard update from server: {} chars, type: {}",
+                  name, clipboard_msg.data.content.len(), clipboard_msg.data.content_type);
+            if config_rx.has_changed().unwrap_or(false) {
+                if let Ok(p) = Pipeline::incoming(&config_rx.borrow_and_update()) { incoming = p; }
+            }
+            if !config_rx.borrow().direction.receives() { info!("  - Send-only, ignoring"); continue; }
+            clipboard_msg.data = match incoming.run(clipboard_msg.data) {
+                Ok(data) => data,
+                Err(reason) => { info!("  - {}, ignoring", reason); continue; }
+            };
+            if clipboard_msg.data.html.is_some() { info!("  - Contains HTML content"); }
+            if clipboard_msg.data.rtf.is_some() { info!("  - Contains RTF content"); }
+            if clipboard_msg.data.image.is_some() { info!("  - Contains image content"); }
+            if !clipboard_msg.data.extra_formats.is_empty() { info!("  - Contains {} other formats", clipboard_msg.data.extra_formats.len()); }
+
+            let result = ctx.clipboard.lock().unwrap().set_clipboard_data_from_server(&clipboard_msg.data);
+            match result {
+                Ok(local) => {
+                    info!("Successfully updated local clipboard (smart mode)");
+                    holding = clipboard_msg.data.seq.map(|seq| (seq, local));
+                }
+                Err(e) => error!("Failed to set clipboard: {}", e),
+            }
+        } else if let Ok(notice) = serde_json::from_str::<ServerNotice>(&text) {
+            if notice.msg_type == "server_shutdown" {
+                info!("[{}] Server is shutting down", name);
+                return Ok(notice.reconnect_after.map(Duration::from_secs));
+            }
+            if notice.msg_type == "clipboard_clear" {
+                let Some((_, local)) = holding.take_if(|(seq, _)| notice.seq == Some(*seq)) else { continue };
+                clear_if_holding(name, local, ctx);
+            }
+        }
+    }
+    Ok(None)
+}
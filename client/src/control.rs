@@ -0,0 +1,125 @@
+// Local control channel between one-shot commands and the running daemon.
+// A Unix socket in the user's runtime directory (a named pipe on Windows) that
+// accepts one line per request and answers "ok" or "error: <reason>":
+//   pause [SECONDS]   stop syncing, optionally resuming after SECONDS
+//   resume            start syncing again
+//   send-held         send content held back as sensitive
+
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tracing::{debug, warn};
+
+use crate::Command;
+
+type ControlResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync>>;
+
+#[cfg(unix)]
+pub fn socket_path() -> Option<std::path::PathBuf> {
+    // The runtime directory is private to the user; fall back to the cache directory
+    let dir = dirs::runtime_dir().or_else(dirs::cache_dir)?.join("shared-clipboard");
+    let _ = std::fs::create_dir_all(&dir);
+    Some(dir.join("control.sock"))
+}
+
+#[cfg(windows)]
+fn pipe_name() -> String {
+    format!(r"\\.\pipe\shared-clipboard-{}", std::env::var("USERNAME").unwrap_or_default())
+}
+
+// Listen for control requests and forward them to the daemon's command loop.
+pub fn serve(cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>) {
+    tokio::spawn(async move {
+        if let Err(e) = listen(cmd_tx).await {
+            warn!("Control socket unavailable, command-line pause/resume/send-held will not reach this client: {}", e);
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn listen(cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>) -> ControlResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::net::{UnixListener, UnixStream};
+
+    let path = socket_path().ok_or("no runtime or cache directory")?;
+    if path.exists() {
+        // A socket that still answers belongs to another daemon; anything else is left over
+        if UnixStream::connect(&path).await.is_ok() {
+            return Err(format!("{} is in use by another client", path.display()).into());
+        }
+        std::fs::remove_file(&path)?;
+    }
+    let listener = UnixListener::bind(&path)?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+    debug!("Control socket listening at {}", path.display());
+    loop {
+        let (stream, _) = listener.accept().await?;
+        tokio::spawn(handle(stream, cmd_tx.clone()));
+    }
+}
+
+#[cfg(windows)]
+async fn listen(cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>) -> ControlResult<()> {
+    use tokio::net::windows::named_pipe::ServerOptions;
+
+    let name = pipe_name();
+    let mut server = ServerOptions::new().first_pipe_instance(true).create(&name)?;
+    debug!("Control pipe listening at {}", name);
+    loop {
+        server.connect().await?;
+        let stream = std::mem::replace(&mut server, ServerOptions::new().create(&name)?);
+        tokio::spawn(handle(stream, cmd_tx.clone()));
+    }
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(stream: S, cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>) {
+    let mut stream = BufReader::new(stream);
+    let mut line = String::new();
+    if stream.read_line(&mut line).await.is_err() { return; }
+    let reply = match parse_request(line.trim()) {
+        Ok(cmd) => match cmd_tx.send(cmd) {
+            Ok(()) => "ok".to_string(),
+            Err(_) => "error: client is shutting down".to_string(),
+        },
+        Err(e) => format!("error: {}", e),
+    };
+    let _ = stream.get_mut().write_all(format!("{}\n", reply).as_bytes()).await;
+}
+
+fn parse_request(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let cmd = match (words.next(), words.next()) {
+        (Some("pause"), None) => Command::Pause(None),
+        (Some("pause"), Some(secs)) => {
+            let secs: u64 = secs.parse().map_err(|_| format!("invalid duration '{}'", secs))?;
+            Command::Pause(Some(Duration::from_secs(secs)))
+        }
+        (Some("resume"), None) => Command::Resume,
+        (Some("send-held"), None) => Command::SendHeld,
+        _ => return Err(format!("unknown request '{}'", line)),
+    };
+    if words.next().is_some() { return Err(format!("unknown request '{}'", line)); }
+    Ok(cmd)
+}
+
+// Send one request to the running daemon and return an error unless it answers "ok".
+pub async fn request(line: &str) -> ControlResult<()> {
+    #[cfg(unix)]
+    let stream = {
+        let path = socket_path().ok_or("no runtime or cache directory")?;
+        tokio::net::UnixStream::connect(&path).await
+            .map_err(|e| format!("cannot reach the running client at {}: {}", path.display(), e))?
+    };
+    #[cfg(windows)]
+    let stream = tokio::net::windows::named_pipe::ClientOptions::new().open(pipe_name())
+        .map_err(|e| format!("cannot reach the running client: {}", e))?;
+
+    let mut stream = BufReader::new(stream);
+    stream.get_mut().write_all(format!("{}\n", line).as_bytes()).await?;
+    let mut reply = String::new();
+    stream.read_line(&mut reply).await?;
+    match reply.trim() {
+        "ok" => Ok(()),
+        "" => Err("the running client closed the connection".into()),
+        other => Err(other.strip_prefix("error: ").unwrap_or(other).to_string().into()),
+    }
+}
@@ -0,0 +1,655 @@
+// Conversions between clipboard representations, used to fill in the ones an update lacks:
+// HTML → plain text and RTF, RTF → plain text and HTML.
+//
+// HTML is read with html5ever's tokenizer and laid out once into a flat list of events
+// (text, paragraph and line breaks, list items, table cells, styles), which the plain text
+// and RTF writers render. RTF is read by a small reader that understands the control words
+// editors use for text, paragraphs and bold/italic/underline, and skips everything else.
+
+use std::cell::RefCell;
+
+use html5ever::tendril::StrTendril;
+use html5ever::tokenizer::{BufferQueue, TagKind, Token, TokenSink, TokenSinkResult, Tokenizer};
+
+use crate::ClipboardData;
+
+// Derive every missing representation from the ones present. Formats the receiving
+// platform cannot write are ignored by its clipboard backend.
+pub fn complete(data: &mut ClipboardData) {
+    fill_text(data);
+    match (&data.html, &data.rtf) {
+        (Some(html), None) => data.rtf = Some(html_to_rtf(html)),
+        (None, Some(rtf)) => data.html = Some(rtf_to_html(rtf)),
+        _ => {}
+    }
+}
+
+// Derive the plain text when it is missing, or when it is only a copy of the HTML source
+// (Linux apps that offer nothing but text/html).
+pub fn fill_text(data: &mut ClipboardData) {
+    let content = data.content.trim();
+    if let Some(html) = &data.html {
+        if content.is_empty() || content == html.trim() {
+            data.content = html_to_text(html);
+        }
+    } else if let Some(rtf) = &data.rtf {
+        if content.is_empty() {
+            data.content = rtf_to_text(rtf);
+        }
+    }
+}
+
+pub fn html_to_text(html: &str) -> String {
+    let mut out = String::new();
+    let mut links = Vec::new();
+    for event in layout(html) {
+        match event {
+            Event::Text(text) => out.push_str(&text),
+            Event::Break(lines) => out.push_str(&"\n".repeat(lines)),
+            Event::LineBreak => out.push('\n'),
+            Event::Item { depth, number } => {
+                out.push_str(&"  ".repeat(depth));
+                match number {
+                    Some(n) => out.push_str(&format!("{}. ", n)),
+                    None => out.push_str("- "),
+                }
+            }
+            Event::Cell => out.push('\t'),
+            Event::Open(Style::Link(_)) => links.push(out.len()),
+            Event::Close(Style::Link(href)) => {
+                // Links keep their target unless the text already shows it
+                let Some(start) = links.pop() else { continue };
+                let label = out[start..].trim();
+                let shown = |target: &str| label.trim_end_matches('/') == target.trim_end_matches('/');
+                if label.is_empty() {
+                    out.push_str(&href);
+                } else if is_external(&href) && !shown(&href) && !shown(href.trim_start_matches("mailto:")) {
+                    out.push_str(&format!(" ({})", href));
+                }
+            }
+            Event::Open(_) | Event::Close(_) => {}
+        }
+    }
+    tidy(&out)
+}
+
+pub fn html_to_rtf(html: &str) -> String {
+    let mut out = String::from("{\\rtf1\\ansi\\ansicpg1252\\deff0{\\fonttbl{\\f0\\fswiss Helvetica;}{\\f1\\fmodern Courier New;}}\n\\f0\\fs24 ");
+    for event in layout(html) {
+        match event {
+            Event::Text(text) => escape_rtf(&text, &mut out),
+            Event::Break(lines) => out.push_str(&"\\par\n".repeat(lines)),
+            Event::LineBreak => out.push_str("\\line "),
+            Event::Item { depth, number } => {
+                out.push_str(&"\\tab ".repeat(depth));
+                match number {
+                    Some(n) => out.push_str(&format!("{}.\\tab ", n)),
+                    None => out.push_str("\\bullet\\tab "),
+                }
+            }
+            Event::Cell => out.push_str("\\tab "),
+            Event::Open(style) => out.push_str(&match style {
+                Style::Bold => "{\\b ".to_string(),
+                Style::Italic => "{\\i ".to_string(),
+                Style::Underline => "{\\ul ".to_string(),
+                Style::Strike => "{\\strike ".to_string(),
+                Style::Mono => "{\\f1 ".to_string(),
+                Style::Heading(level) => format!("{{\\b\\fs{} ", [36, 32, 28, 26, 24, 24][level.clamp(1, 6) - 1]),
+                Style::Link(href) => {
+                    let mut target = String::new();
+                    escape_rtf(&href.replace('"', "%22"), &mut target);
+                    format!("{{\\field{{\\*\\fldinst{{HYPERLINK \"{}\"}}}}{{\\fldrslt{{\\ul ", target)
+                }
+            }),
+            Event::Close(Style::Link(_)) => out.push_str("}}}"),
+            Event::Close(_) => out.push('}'),
+        }
+    }
+    out.push('}');
+    out
+}
+
+pub fn rtf_to_text(rtf: &str) -> String {
+    let mut out = String::new();
+    for piece in read_rtf(rtf) {
+        match piece {
+            RtfPiece::Text(text, _) => out.push_str(&text),
+            RtfPiece::Par | RtfPiece::Line => out.push('\n'),
+        }
+    }
+    tidy(&out)
+}
+
+pub fn rtf_to_html(rtf: &str) -> String {
+    let mut out = String::new();
+    let mut paragraph = String::new();
+    for piece in read_rtf(rtf) {
+        match piece {
+            RtfPiece::Text(text, format) => {
+                let mut text = escape_html(&text);
+                if format.underline { text = format!("<u>{}</u>", text); }
+                if format.italic { text = format!("<i>{}</i>", text); }
+                if format.bold { text = format!("<b>{}</b>", text); }
+                paragraph.push_str(&text);
+            }
+            RtfPiece::Line => paragraph.push_str("<br>"),
+            RtfPiece::Par => {
+                if !paragraph.trim().is_empty() { out.push_str(&format!("<p>{}</p>", paragraph.trim_end())); }
+                paragraph.clear();
+            }
+        }
+    }
+    if !paragraph.trim().is_empty() { out.push_str(&format!("<p>{}</p>", paragraph.trim_end())); }
+    out
+}
+
+fn is_external(href: &str) -> bool {
+    let lower = href.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:")
+}
+
+// Trailing spaces on every line and blank lines at either end removed.
+fn tidy(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_matches('\n').to_string()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn escape_rtf(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '\\' | '{' | '}' => { out.push('\\'); out.push(c); }
+            '\t' => out.push_str("\\tab "),
+            '\n' => out.push_str("\\line "),
+            c if c.is_ascii() => out.push(c),
+            // \uN takes a signed 16-bit code unit, followed by a '?' fallback for old readers
+            c => for unit in c.encode_utf16(&mut [0; 2]) { out.push_str(&format!("\\u{}?", *unit as i16)); },
+        }
+    }
+}
+
+// HTML layout
+
+#[derive(Debug, Clone, PartialEq)]
+enum Style {
+    Bold,
+    Italic,
+    Underline,
+    Strike,
+    Mono,
+    Heading(usize),
+    Link(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    // Whitespace already collapsed, never at the start of a line
+    Text(String),
+    // End the line (1) or the paragraph (2)
+    Break(usize),
+    // <br>
+    LineBreak,
+    // Start of a list item; `number` is set in ordered lists
+    Item { depth: usize, number: Option<usize> },
+    // Between table cells
+    Cell,
+    Open(Style),
+    Close(Style),
+}
+
+enum Piece {
+    Text(String),
+    // Tag name and its `href` (links) or `alt` (images)
+    Start(String, Option<String>),
+    End(String),
+}
+
+// Collects tokens; html5ever has already decoded entities in the text.
+#[derive(Default)]
+struct Collector {
+    pieces: RefCell<Vec<Piece>>,
+}
+
+impl TokenSink for Collector {
+    type Handle = ();
+
+    fn process_token(&self, token: Token, _line_number: u64) -> TokenSinkResult<()> {
+        let mut pieces = self.pieces.borrow_mut();
+        match token {
+            Token::CharacterTokens(text) => pieces.push(Piece::Text(text.to_string())),
+            Token::TagToken(tag) => {
+                let name = tag.name.to_string();
+                match tag.kind {
+                    TagKind::StartTag => {
+                        let wanted = if name == "img" { "alt" } else { "href" };
+                        let attr = tag.attrs.iter().find(|a| &*a.name.local == wanted).map(|a| a.value.to_string());
+                        pieces.push(Piece::Start(name, attr));
+                    }
+                    TagKind::EndTag => pieces.push(Piece::End(name)),
+                }
+            }
+            _ => {}
+        }
+        TokenSinkResult::Continue
+    }
+}
+
+const SKIPPED: &[&str] = &["script", "style", "head", "title", "template", "noscript"];
+const PARAGRAPHS: &[&str] = &["p", "h1", "h2", "h3", "h4", "h5", "h6", "blockquote", "pre", "table", "dl", "figure", "hr"];
+const LINES: &[&str] = &[
+    "div", "section", "article", "header", "footer", "nav", "aside", "main", "address", "tr", "li", "dt", "dd", "figcaption",
+];
+
+#[derive(Default)]
+struct Layout {
+    events: Vec<Event>,
+    // Line breaks owed before the next text
+    breaks: usize,
+    space: bool,
+    line_start: bool,
+    started: bool,
+    item: Option<(usize, Option<usize>)>,
+    cell: bool,
+}
+
+impl Layout {
+    // Emit the breaks, list marker and cell separator owed before new content.
+    fn flush(&mut self) {
+        if self.started && self.breaks > 0 {
+            self.events.push(Event::Break(self.breaks));
+            self.line_start = true;
+        }
+        self.breaks = 0;
+        if let Some((depth, number)) = self.item.take() {
+            self.events.push(Event::Item { depth, number });
+            self.line_start = true;
+        }
+        if std::mem::take(&mut self.cell) {
+            self.events.push(Event::Cell);
+            self.line_start = true;
+        }
+        self.started = true;
+    }
+
+    fn text(&mut self, text: &str) {
+        self.flush();
+        let mut text = text.to_string();
+        if self.space && !self.line_start { text.insert(0, ' '); }
+        self.space = false;
+        self.line_start = false;
+        match self.events.last_mut() {
+            Some(Event::Text(last)) => last.push_str(&text),
+            _ => self.events.push(Event::Text(text)),
+        }
+    }
+
+    // Pending space goes before the style starts, not inside it.
+    fn open(&mut self, style: Style) {
+        if self.space && self.started && self.breaks == 0 && !self.line_start { self.text(""); }
+        self.flush();
+        self.events.push(Event::Open(style));
+    }
+
+    fn block(&mut self, lines: usize) {
+        self.breaks = self.breaks.max(lines);
+        self.space = false;
+    }
+
+    fn line_break(&mut self) {
+        if self.started && self.breaks == 0 { self.events.push(Event::LineBreak); }
+        self.line_start = true;
+        self.space = false;
+    }
+}
+
+fn layout(html: &str) -> Vec<Event> {
+    let input = BufferQueue::default();
+    input.push_back(StrTendril::from_slice(html));
+    let tokenizer = Tokenizer::new(Collector::default(), Default::default());
+    let _ = tokenizer.feed(&input);
+    tokenizer.end();
+    let pieces = tokenizer.sink.pieces.take();
+
+    let mut out = Layout::default();
+    let (mut skip, mut pre) = (0usize, 0usize);
+    // One entry per open list, with the next number for ordered ones
+    let mut lists: Vec<Option<usize>> = Vec::new();
+    let mut styles: Vec<(String, Style)> = Vec::new();
+    let mut cells = 0;
+    for piece in pieces {
+        match piece {
+            Piece::Text(_) if skip > 0 => {}
+            Piece::Text(text) if pre > 0 => {
+                for (i, line) in text.split('\n').enumerate() {
+                    if i > 0 { out.line_break(); }
+                    if !line.is_empty() { out.text(line); }
+                }
+            }
+            Piece::Text(text) => {
+                // Only ASCII whitespace collapses; &nbsp; stays
+                if text.starts_with(|c: char| c.is_ascii_whitespace()) { out.space = true; }
+                let words: Vec<&str> = text.split_ascii_whitespace().collect();
+                if !words.is_empty() { out.text(&words.join(" ")); }
+                if text.ends_with(|c: char| c.is_ascii_whitespace()) { out.space = true; }
+            }
+            Piece::Start(name, _) if SKIPPED.contains(&name.as_str()) => skip += 1,
+            Piece::End(name) if SKIPPED.contains(&name.as_str()) => skip = skip.saturating_sub(1),
+            Piece::Start(_, _) | Piece::End(_) if skip > 0 => {}
+            Piece::Start(name, attr) => {
+                match name.as_str() {
+                    "br" => out.line_break(),
+                    "ul" | "ol" => {
+                        out.block(if lists.is_empty() { 2 } else { 1 });
+                        lists.push((name == "ol").then_some(1));
+                    }
+                    "li" => {
+                        out.block(1);
+                        let depth = lists.len().saturating_sub(1);
+                        let number = lists.last_mut().and_then(|n| n.as_mut()).map(|n| { *n += 1; *n - 1 });
+                        out.item = Some((depth, number));
+                    }
+                    "tr" => { out.block(1); cells = 0; }
+                    "td" | "th" => { out.cell = cells > 0; cells += 1; }
+                    "img" => if let Some(alt) = attr.as_deref().filter(|a| !a.trim().is_empty()) { out.text(alt.trim()); },
+                    name if PARAGRAPHS.contains(&name) => out.block(2),
+                    name if LINES.contains(&name) => out.block(1),
+                    _ => {}
+                }
+                if name == "pre" { pre += 1; }
+                let style = match name.as_str() {
+                    "b" | "strong" => Some(Style::Bold),
+                    "i" | "em" | "cite" | "var" => Some(Style::Italic),
+                    "u" | "ins" => Some(Style::Underline),
+                    "s" | "strike" | "del" => Some(Style::Strike),
+                    "code" | "kbd" | "samp" | "tt" | "pre" => Some(Style::Mono),
+                    "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => Some(Style::Heading(name[1..].parse().unwrap_or(1))),
+                    "a" => attr.filter(|href| !href.trim().is_empty()).map(|href| Style::Link(href.trim().to_string())),
+                    _ => None,
+                };
+                if let Some(style) = style {
+                    out.open(style.clone());
+                    styles.push((name, style));
+                }
+            }
+            Piece::End(name) => {
+                match name.as_str() {
+                    "ul" | "ol" => { lists.pop(); out.block(if lists.is_empty() { 2 } else { 1 }); }
+                    "pre" => { pre = pre.saturating_sub(1); out.block(2); }
+                    name if PARAGRAPHS.contains(&name) => out.block(2),
+                    name if LINES.contains(&name) => out.block(1),
+                    _ => {}
+                }
+                // Close styles opened inside an element that was left unclosed, too
+                if styles.iter().any(|(open, _)| *open == name) {
+                    while let Some((open, style)) = styles.pop() {
+                        out.events.push(Event::Close(style));
+                        if open == name { break; }
+                    }
+                }
+            }
+        }
+    }
+    while let Some((_, style)) = styles.pop() {
+        out.events.push(Event::Close(style));
+    }
+    out.events
+}
+
+// RTF reading
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Format {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum RtfPiece {
+    Text(String, Format),
+    Par,
+    Line,
+}
+
+#[derive(Clone, Copy)]
+struct Group {
+    format: Format,
+    // Inside a destination whose text is not content (font table, pictures, field codes...)
+    skip: bool,
+    // Fallback characters that follow each \uN
+    uc: usize,
+}
+
+// Destinations that hold no visible text.
+const DESTINATIONS: &[&str] = &[
+    "fonttbl", "colortbl", "stylesheet", "info", "pict", "header", "footer", "headerl", "headerr", "footerl", "footerr",
+    "footnote", "object", "themedata", "colorschememapping", "datastore", "latentstyles", "listtable",
+    "listoverridetable", "rsidtbl", "xmlnstbl", "generator", "filetbl", "revtbl", "pgdsctbl", "fldinst", "mmathPr",
+];
+
+// Windows-1252 characters in 0x80..0x9F, where it differs from Latin-1
+const CP1252: [char; 32] = [
+    '€', '\u{81}', '‚', 'ƒ', '„', '…', '†', '‡', 'ˆ', '‰', 'Š', '‹', 'Œ', '\u{8d}', 'Ž', '\u{8f}',
+    '\u{90}', '‘', '’', '“', '”', '•', '–', '—', '˜', '™', 'š', '›', 'œ', '\u{9d}', 'ž', 'Ÿ',
+];
+
+fn read_rtf(rtf: &str) -> Vec<RtfPiece> {
+    let mut pieces: Vec<RtfPiece> = Vec::new();
+    let mut group = Group { format: Format::default(), skip: false, uc: 1 };
+    let mut stack: Vec<Group> = Vec::new();
+    // Fallback characters still to drop after a \uN
+    let mut fallback = 0usize;
+    let mut high_surrogate: Option<u16> = None;
+
+    let emit = |pieces: &mut Vec<RtfPiece>, group: &Group, c: char| {
+        if group.skip { return; }
+        match pieces.last_mut() {
+            Some(RtfPiece::Text(text, format)) if *format == group.format => text.push(c),
+            _ => pieces.push(RtfPiece::Text(c.to_string(), group.format)),
+        }
+    };
+
+    let mut chars = rtf.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => { stack.push(group); fallback = 0; }
+            '}' => { if let Some(outer) = stack.pop() { group = outer; } fallback = 0; }
+            '\r' | '\n' => {}
+            '\\' => {
+                let Some(next) = chars.next() else { break };
+                match next {
+                    '\\' | '{' | '}' => {
+                        if fallback > 0 { fallback -= 1; } else { emit(&mut pieces, &group, next); }
+                    }
+                    '*' => group.skip = true,
+                    '~' => emit(&mut pieces, &group, '\u{a0}'),
+                    '_' => emit(&mut pieces, &group, '-'),
+                    '\r' | '\n' if !group.skip => pieces.push(RtfPiece::Par),
+                    '\'' => {
+                        let hex: String = chars.by_ref().take(2).collect();
+                        if fallback > 0 { fallback -= 1; continue; }
+                        let Ok(byte) = u8::from_str_radix(&hex, 16) else { continue };
+                        let c = match byte {
+                            0x80..=0x9f => CP1252[(byte - 0x80) as usize],
+                            _ => byte as char,
+                        };
+                        emit(&mut pieces, &group, c);
+                    }
+                    c if c.is_ascii_alphabetic() => {
+                        let mut word = c.to_string();
+                        while let Some(&c) = chars.peek() {
+                            if !c.is_ascii_alphabetic() { break; }
+                            word.push(c);
+                            chars.next();
+                        }
+                        let mut digits = String::new();
+                        if chars.peek() == Some(&'-') { digits.push('-'); chars.next(); }
+                        while let Some(&c) = chars.peek() {
+                            if !c.is_ascii_digit() { break; }
+                            digits.push(c);
+                            chars.next();
+                        }
+                        let param: Option<i32> = digits.parse().ok();
+                        // A single space ends the control word and is not text
+                        if chars.peek() == Some(&' ') { chars.next(); }
+
+                        let symbol = match word.as_str() {
+                            "par" | "sect" | "page" => { if !group.skip { pieces.push(RtfPiece::Par); } None }
+                            "line" => { if !group.skip { pieces.push(RtfPiece::Line); } None }
+                            "tab" | "cell" => Some('\t'),
+                            "row" => { if !group.skip { pieces.push(RtfPiece::Line); } None }
+                            "bullet" => Some('•'),
+                            "emdash" => Some('—'),
+                            "endash" => Some('–'),
+                            "lquote" => Some('‘'),
+                            "rquote" => Some('’'),
+                            "ldblquote" => Some('“'),
+                            "rdblquote" => Some('”'),
+                            "emspace" | "enspace" | "qmspace" => Some(' '),
+                            "b" => { group.format.bold = param != Some(0); None }
+                            "i" => { group.format.italic = param != Some(0); None }
+                            "ul" => { group.format.underline = param != Some(0); None }
+                            "ulnone" => { group.format.underline = false; None }
+                            "plain" => { group.format = Format::default(); None }
+                            "uc" => { group.uc = param.unwrap_or(1).max(0) as usize; None }
+                            "u" => {
+                                let unit = param.unwrap_or(0) as i16 as u16;
+                                fallback = group.uc;
+                                match (high_surrogate.take(), unit) {
+                                    (_, 0xd800..=0xdbff) => { high_surrogate = Some(unit); None }
+                                    (Some(high), 0xdc00..=0xdfff) => char::decode_utf16([high, unit]).next().and_then(Result::ok),
+                                    (_, unit) => char::from_u32(unit as u32),
+                                }
+                            }
+                            word if DESTINATIONS.contains(&word) => { group.skip = true; None }
+                            _ => None,
+                        };
+                        if let Some(c) = symbol { emit(&mut pieces, &group, c); }
+                    }
+                    _ => {}
+                }
+            }
+            c => {
+                if fallback > 0 { fallback -= 1; } else { emit(&mut pieces, &group, c); }
+            }
+        }
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_to_text_lays_out_blocks_and_collapses_whitespace() {
+        let html = "<h1>Title</h1>\n  <p>First   line<br>second\nline</p><div>Next</div><p>Last</p>";
+        assert_eq!(html_to_text(html), "Title\n\nFirst line\nsecond line\n\nNext\n\nLast");
+    }
+
+    #[test]
+    fn html_to_text_renders_lists() {
+        let html = "<ul><li>one</li><li>two<ol><li>a</li><li>b</li></ol></li></ul><p>after</p>";
+        assert_eq!(html_to_text(html), "- one\n- two\n  1. a\n  2. b\n\nafter");
+    }
+
+    #[test]
+    fn html_to_text_keeps_link_targets() {
+        let html = r#"See <a href="https://example.com/docs">the docs</a>, <a href="https://example.com/">https://example.com</a> and <a href="/relative">here</a>."#;
+        assert_eq!(html_to_text(html), "See the docs (https://example.com/docs), https://example.com and here.");
+    }
+
+    #[test]
+    fn html_to_text_decodes_entities_and_skips_scripts() {
+        let html = "<style>p { color: red }</style><p>Fish &amp; chips&nbsp;&lt;3</p><script>alert(1)</script>";
+        assert_eq!(html_to_text(html), "Fish & chips\u{a0}<3");
+    }
+
+    #[test]
+    fn html_to_text_keeps_preformatted_text() {
+        let html = "<pre>fn main() {\n    run();\n}</pre>";
+        assert_eq!(html_to_text(html), "fn main() {\n    run();\n}");
+    }
+
+    #[test]
+    fn html_to_text_separates_table_cells() {
+        let html = "<table><tr><th>Name</th><th>Qty</th></tr><tr><td>Apple</td><td>3</td></tr></table>";
+        assert_eq!(html_to_text(html), "Name\tQty\nApple\t3");
+    }
+
+    #[test]
+    fn html_to_rtf_maps_styles_and_paragraphs() {
+        let rtf = html_to_rtf("<p>Hello <b>bold</b> <i>it</i></p><p>Second</p>");
+        assert!(rtf.starts_with("{\\rtf1"), "{}", rtf);
+        assert!(rtf.contains("Hello {\\b bold} {\\i it}\\par\n\\par\nSecond}"), "{}", rtf);
+    }
+
+    #[test]
+    fn html_to_rtf_escapes_text() {
+        let rtf = html_to_rtf("{braces} back\\slash café 😀");
+        assert!(rtf.contains("\\{braces\\} back\\\\slash caf\\u233? \\u-10179?\\u-8704?"), "{}", rtf);
+    }
+
+    #[test]
+    fn html_to_rtf_writes_links_as_fields() {
+        let rtf = html_to_rtf(r#"<a href="https://example.com">site</a>"#);
+        assert!(rtf.contains("{\\field{\\*\\fldinst{HYPERLINK \"https://example.com\"}}{\\fldrslt{\\ul site}}}"), "{}", rtf);
+    }
+
+    #[test]
+    fn html_round_trips_through_rtf() {
+        let html = "<p>One <b>two</b></p><ul><li>café</li></ul>";
+        assert_eq!(rtf_to_text(&html_to_rtf(html)), html_to_text(html).replace("- ", "•\t"));
+    }
+
+    #[test]
+    fn rtf_to_text_skips_tables_and_destinations() {
+        let rtf = r"{\rtf1\ansi{\fonttbl{\f0 Arial;}}{\colortbl;\red255\green0\blue0;}{\*\generator Writer;}\f0 Hello\par {\b World}\line next}";
+        assert_eq!(rtf_to_text(rtf), "Hello\nWorld\nnext");
+    }
+
+    #[test]
+    fn rtf_to_text_decodes_escapes() {
+        let rtf = r"{\rtf1\ansi caf\'e9 \'93quoted\'94 na\u239?ve \uc2\u8364xx euro {\uc1\u-10179?\u-8704?}}";
+        assert_eq!(rtf_to_text(rtf), "café “quoted” naïve € euro 😀");
+    }
+
+    #[test]
+    fn rtf_to_text_reads_field_results() {
+        let rtf = r#"{\rtf1 {\field{\*\fldinst{HYPERLINK "https://example.com"}}{\fldrslt{\ul site}}}}"#;
+        assert_eq!(rtf_to_text(rtf), "site");
+    }
+
+    #[test]
+    fn rtf_to_html_keeps_basic_formatting() {
+        let rtf = r"{\rtf1\ansi Plain {\b bold \i both}\i0  and \ul under\ulnone\par 1 < 2\line x}";
+        assert_eq!(rtf_to_html(rtf), "<p>Plain <b>bold </b><b><i>both</i></b> and <u>under</u></p><p>1 &lt; 2<br>x</p>");
+    }
+
+    fn data(content: &str, html: Option<&str>, rtf: Option<&str>) -> ClipboardData {
+        ClipboardData { html: html.map(String::from), rtf: rtf.map(String::from), ..ClipboardData::text(content) }
+    }
+
+    #[test]
+    fn complete_fills_missing_representations() {
+        let mut from_html = data("<b>hi</b>", Some("<b>hi</b>"), None);
+        complete(&mut from_html);
+        assert_eq!(from_html.content, "hi");
+        assert!(from_html.rtf.unwrap().contains("{\\b hi}"));
+
+        let mut from_rtf = data("", None, Some(r"{\rtf1 {\b hi}}"));
+        complete(&mut from_rtf);
+        assert_eq!(from_rtf.content, "hi");
+        assert_eq!(from_rtf.html.as_deref(), Some("<p><b>hi</b></p>"));
+    }
+
+    #[test]
+    fn complete_keeps_existing_representations() {
+        let mut full = data("hi", Some("<i>hi</i>"), Some(r"{\rtf1 hi}"));
+        complete(&mut full);
+        assert_eq!((full.content.as_str(), full.html.as_deref(), full.rtf.as_deref()), ("hi", Some("<i>hi</i>"), Some(r"{\rtf1 hi}")));
+    }
+}
@@ -1,22 +1,28 @@
-use futures_util::StreamExt;
 use reqwest::Client as HttpClient;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::time::{Duration, Instant};
 use tokio::time::interval;
-use tokio_tungstenite::{connect_async, tungstenite::Message};
 use tracing::{debug, error, info, warn};
 use url::Url;
+use clipboard_common::{Content, Fingerprint};
 
 mod clipboard;
-use clipboard::{ClipboardManager, ClipboardBackend};
+use clipboard::{ClipboardBackend, ClipboardManager, SyncEngine};
 
-#[cfg(target_os = "linux")]
 mod tray;
-#[cfg(target_os = "windows")]
+#[cfg(all(target_os = "windows", feature = "tray"))]
 mod tray_win;
 
+#[cfg(feature = "gui")]
 mod settings;
 mod config;
+mod cli;
+mod connections;
+mod control;
+mod sensitive;
+mod pipeline;
+mod convert;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClipboardData {
@@ -30,6 +36,64 @@ struct ClipboardData {
     // Metadata
     content_type: String, // "text", "html", "rtf", "image", "mixed"
     timestamp: u64,
+    // Assigned by the server when the update is published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+    // Seconds until the server clears the entry again
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u64>,
+    // Application the content was copied from, where the client's platform exposes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_app: Option<String>,
+    // Other MIME types carried verbatim, MIME type → base64 payload (see `formats.mime_types`)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extra_formats: BTreeMap<String, String>,
+    // Content fingerprint set by the server; identifies the entry in history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+}
+
+impl ClipboardData {
+    // Computed the same way as the server's, so local and remote content can be compared
+    fn content_fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&Content {
+            text: &self.content,
+            html: self.html.as_deref(),
+            rtf: self.rtf.as_deref(),
+            image: self.image.as_deref(),
+            extra: &self.extra_formats,
+        })
+    }
+
+    // Plain-text entry, as tests build them
+    #[cfg(test)]
+    fn text(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            html: None,
+            rtf: None,
+            image: None,
+            content_type: "text".to_string(),
+            timestamp: 0,
+            seq: None,
+            ttl: None,
+            source_app: None,
+            extra_formats: BTreeMap::new(),
+            fingerprint: None,
+        }
+    }
+
+    // After representations were added or dropped
+    fn update_content_type(&mut self) {
+        self.content_type = match (self.html.is_some(), self.rtf.is_some(), self.image.is_some()) {
+            (true, true, _) => "mixed",
+            (true, false, _) => "html",
+            (false, true, _) => "rtf",
+            (false, false, true) if self.content.is_empty() => "image",
+            (false, false, true) => "mixed",
+            (false, false, false) => "text",
+        }.to_string();
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,39 +103,178 @@ struct ClipboardMessage {
     data: ClipboardData,
 }
 
+// Control frames from the server that carry no clipboard data
+#[derive(Debug, Clone, Deserialize)]
+struct ServerNotice {
+    #[serde(rename = "type")]
+    msg_type: String,
+    // Seconds to wait before reconnecting after a `server_shutdown`
+    reconnect_after: Option<u64>,
+    // Entry that was cleared or expired, for `clipboard_clear`
+    seq: Option<u64>,
+}
+
 #[derive(Clone, Debug)]
-enum Command { SetUrl(String), Quit }
+enum Command {
+    #[cfg(feature = "tray")]
+    SwitchProfile(String),
+    #[cfg(feature = "tray")]
+    SendTo(String),
+    // Send local content held back as sensitive (`sensitive.action = "confirm"`)
+    SendHeld,
+    ReloadConfig,
+    #[cfg(feature = "tray")]
+    SetDirection(config::Direction),
+    // Stop syncing in both directions, optionally resuming after the given time
+    Pause(Option<Duration>),
+    Resume,
+    #[cfg(feature = "tray")]
+    Quit,
+}
 
 struct ClipboardClient {
-    clipboard_manager: Box<dyn ClipboardBackend + Send>,
-    http_client: HttpClient,
-    url_tx: tokio::sync::watch::Sender<String>,
-    url_rx: tokio::sync::watch::Receiver<String>,
-    last_local_content: String,
-    last_local_image: Option<String>,
-    tray: Option<Box<dyn tray::Tray>>,
+    config_rx: tokio::sync::watch::Receiver<config::Config>,
+    clipboard: connections::SharedClipboard,
+    // Last local clipboard content that passed filters, for explicit "Send to" actions
+    last_local: std::sync::Arc<std::sync::Mutex<Option<ClipboardData>>>,
+    // Sensitive local content waiting for confirmation; replaced by the next local change
+    held: std::sync::Arc<std::sync::Mutex<Option<ClipboardData>>>,
+    // While set, local changes are not sent and incoming updates are not applied
+    paused: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    tray: Option<std::sync::Arc<dyn tray::Tray>>,
+    // Command loop; ends on Quit
+    commands: tokio::task::JoinHandle<()>,
 }
 
 impl ClipboardClient {
-    fn new(initial_url: String) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let clipboard_manager = ClipboardManager::new()?;
-        let clipboard_manager: Box<dyn ClipboardBackend + Send> = Box::new(clipboard_manager);
-        let http_client = HttpClient::new();
-        let (url_tx, url_rx) = tokio::sync::watch::channel(initial_url.clone());
+    fn new(config: config::Config, headless: bool) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let clipboard = std::sync::Arc::new(std::sync::Mutex::new(SyncEngine::new(ClipboardManager::new()?)));
+        let last_local = std::sync::Arc::new(std::sync::Mutex::new(None::<ClipboardData>));
+        let held = std::sync::Arc::new(std::sync::Mutex::new(None::<ClipboardData>));
+        let paused = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
         let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::unbounded_channel::<Command>();
 
+        let tray: Option<std::sync::Arc<dyn tray::Tray>> = if headless {
+            info!("Running headless, tray icon disabled");
+            None
+        } else {
+            tray::start(config.active().server_url.clone(), cmd_tx.clone()).map(std::sync::Arc::from)
+        };
+        if let Some(tray) = &tray { update_tray_menu(tray.as_ref(), &config); }
+
+        let (config_tx, config_rx) = tokio::sync::watch::channel(config.clone());
+        let mut reloader = ConfigReloader {
+            file_config: config::load().unwrap_or(config),
+            config_tx,
+            tray: tray.clone(),
+        };
+        spawn_config_watcher(cmd_tx.clone());
+        control::serve(cmd_tx.clone());
+
         // Command loop
-        let url_tx_clone = url_tx.clone();
-        tokio::spawn(async move {
+        let config_rx_for_commands = config_rx.clone();
+        #[cfg(feature = "tray")]
+        let last_local_for_commands = last_local.clone();
+        let held_for_commands = held.clone();
+        let paused_for_commands = paused.clone();
+        let tray_for_commands = tray.clone();
+        let mut resume_timer: Option<tokio::task::JoinHandle<()>> = None;
+        let commands = tokio::spawn(async move {
             while let Some(cmd) = cmd_rx.recv().await {
                 match cmd {
-                    Command::SetUrl(u) => {
-                        // Notify only if actually changed, to avoid endless reconnect loops
-                        let changed = url_tx_clone.send_if_modified(|cur| {
-                            if *cur != u { *cur = u.clone(); true } else { false }
-                        });
-                        // no-op if not changed
+                    #[cfg(feature = "tray")]
+                    Command::SwitchProfile(name) => {
+                        match config::update(|c| if c.profiles.contains_key(&name) { c.active_profile = name.clone() }) {
+                            Ok(c) if c.active_profile == name => {
+                                info!("Switching to profile '{}'", name);
+                                reloader.reload(Some(name));
+                            }
+                            Ok(_) => warn!("No profile named '{}'", name),
+                            Err(e) => error!("Failed to switch profile: {}", e),
+                        }
+                    }
+                    #[cfg(feature = "tray")]
+                    Command::SendTo(name) => {
+                        if paused_for_commands.load(std::sync::atomic::Ordering::Relaxed) {
+                            warn!("Sync is paused, not sending to '{}'", name);
+                            continue;
+                        }
+                        let config = config_rx_for_commands.borrow().clone();
+                        if !config.direction.sends() {
+                            warn!("This machine is receive-only, not sending to '{}'", name);
+                            continue;
+                        }
+                        let Some(data) = last_local_for_commands.lock().unwrap().clone() else {
+                            warn!("Nothing to send to '{}' yet", name);
+                            continue;
+                        };
+                        let Some(profile) = config.profiles.get(&name) else {
+                            warn!("No profile named '{}'", name);
+                            continue;
+                        };
+                        let result = match http_client(&config, profile) {
+                            Ok(client) => post_clipboard(&client, &profile.server_url, &data).await,
+                            Err(e) => Err(e),
+                        };
+                        match result {
+                            Ok(()) => info!("Sent clipboard to '{}'", name),
+                            Err(e) => warn!("Failed to send clipboard to '{}': {}", name, e),
+                        }
+                    }
+                    Command::SendHeld => {
+                        let config = config_rx_for_commands.borrow().clone();
+                        if paused_for_commands.load(std::sync::atomic::Ordering::Relaxed) || !config.direction.sends() {
+                            warn!("Sending is paused or disabled, keeping the held clipboard");
+                            continue;
+                        }
+                        let Some(data) = held_for_commands.lock().unwrap().take() else {
+                            warn!("No sensitive clipboard content is held");
+                            continue;
+                        };
+                        if let Some(tray) = &tray_for_commands { tray.set_held(None); }
+                        for (name, http_client, base) in send_targets(&config) {
+                            match post_clipboard(&http_client, &base, &data).await {
+                                Ok(()) => info!("Sent held clipboard to '{}'", name),
+                                Err(e) => warn!("Failed to send clipboard to '{}': {}", name, e),
+                            }
+                        }
+                    }
+                    Command::ReloadConfig => reloader.reload(None),
+                    #[cfg(feature = "tray")]
+                    Command::SetDirection(direction) => {
+                        match config::update(|c| c.direction = direction) {
+                            Ok(_) => {
+                                info!("Sync direction: {}", direction.label());
+                                reloader.reload(None);
+                            }
+                            Err(e) => error!("Failed to change sync direction: {}", e),
+                        }
+                    }
+                    Command::Pause(resume_after) => {
+                        // A new pause replaces any earlier auto-resume
+                        if let Some(timer) = resume_timer.take() { timer.abort(); }
+                        paused_for_commands.store(true, std::sync::atomic::Ordering::Relaxed);
+                        if let Some(tray) = &tray_for_commands { tray.set_paused(true); }
+                        match resume_after {
+                            Some(delay) => {
+                                info!("Sync paused for {:?}", delay);
+                                let cmd_tx = cmd_tx.clone();
+                                resume_timer = Some(tokio::spawn(async move {
+                                    tokio::time::sleep(delay).await;
+                                    let _ = cmd_tx.send(Command::Resume);
+                                }));
+                            }
+                            None => info!("Sync paused"),
+                        }
                     }
+                    Command::Resume => {
+                        if let Some(timer) = resume_timer.take() { timer.abort(); }
+                        if paused_for_commands.swap(false, std::sync::atomic::Ordering::Relaxed) {
+                            info!("Sync resumed");
+                        }
+                        if let Some(tray) = &tray_for_commands { tray.set_paused(false); }
+                    }
+                    #[cfg(feature = "tray")]
                     Command::Quit => {
                         break;
                     }
@@ -79,23 +282,19 @@ impl ClipboardClient {
             }
         });
 
-        #[cfg(target_os = "linux")]
-        let tray: Option<Box<dyn tray::Tray>> = Some(Box::new(tray::start_tray(initial_url.clone(), cmd_tx.clone())));
-        #[cfg(target_os = "windows")]
-        let tray: Option<Box<dyn tray::Tray>> = Some(Box::new(tray_win::start_tray(initial_url.clone(), cmd_tx.clone())));
-
         Ok(Self {
-            clipboard_manager,
-            http_client,
-            url_tx,
-            url_rx,
-            last_local_content: String::new(),
-            last_local_image: None,
+            config_rx,
+            clipboard,
+            last_local,
+            held,
+            paused,
             tray,
+            commands,
         })
     }
 
-    async fn start(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Monitor the local clipboard and keep the joined profiles connected until the monitor stops.
+    async fn run(&mut self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         info!("Starting clipboard client daemon");
         
         // On Linux, ensure DISPLAY is set for X11 clipboard access
@@ -105,40 +304,40 @@ impl ClipboardClient {
             warn!("Try running: export DISPLAY=:0 before starting the client");
         }
 
-        // Get initial clipboard content
-        if let Ok(clipboard_data) = self.clipboard_manager.get_clipboard_data() {
-            self.last_local_content = clipboard_data.content;
-            self.last_local_image = clipboard_data.image.clone();
-        }
-
-        // Connect to WebSocket
-        let current_url = self.url_rx.borrow().clone();
-        let ws_url = format!("ws://{}/ws", current_url.replace("http://", "").replace("https://", ""));
-        let url = Url::parse(&ws_url)?;
-        
-        let (ws_stream, _) = connect_async(url).await?;
-        info!("Connected to WebSocket server");
-        
-        // Update tray connectivity status
-        if let Some(tray) = &self.tray { tray.set_connected(true); }
-        
-        let (_ws_sender, mut ws_receiver) = ws_stream.split();
-
-        // Create shared clipboard manager for both tasks
-        let shared_clipboard_manager = std::sync::Arc::new(std::sync::Mutex::new(ClipboardManager::new().unwrap()));
-        
         // Start clipboard monitoring task
-        let clipboard_manager_for_monitor = shared_clipboard_manager.clone();
-        let http_client = self.http_client.clone();
-        let url_rx_for_monitor = self.url_rx.clone();
+        let clipboard_for_monitor = self.clipboard.clone();
+        let last_local_for_monitor = self.last_local.clone();
+        let paused_for_monitor = self.paused.clone();
+        let held_for_monitor = self.held.clone();
+        let tray_for_monitor = self.tray.clone();
+        let mut config_rx_for_monitor = self.config_rx.clone();
+        let mut config_for_monitor = config_rx_for_monitor.borrow_and_update().clone();
+        // Patterns were validated when the config was loaded
+        let mut outgoing = pipeline::Pipeline::outgoing(&config_for_monitor).map_err(|(field, message)| format!("{}: {}", field, message))?;
+        let mut detector = sensitive::Detector::new(&config_for_monitor.sensitive)?;
+        let mut targets = send_targets(&config_for_monitor);
+        self.clipboard.lock().unwrap().backend_mut().configure(&config_for_monitor.formats);
         
         let monitor_task = tokio::spawn(async move {
-            let mut interval = interval(Duration::from_millis(100)); // frequent polling
+            let mut interval = interval(Duration::from_millis(config_for_monitor.poll_interval_ms));
             let mut last_post: Option<Instant> = None;
             const MIN_POST_INTERVAL: Duration = Duration::from_millis(200);
             
             loop {
                 interval.tick().await;
+
+                // Pick up reloaded pipeline stages, detectors, profiles and interval
+                if config_rx_for_monitor.has_changed().unwrap_or(false) {
+                    let new_config = config_rx_for_monitor.borrow_and_update().clone();
+                    if new_config.poll_interval_ms != config_for_monitor.poll_interval_ms {
+                        interval = tokio::time::interval(Duration::from_millis(new_config.poll_interval_ms));
+                    }
+                    if let Ok(p) = pipeline::Pipeline::outgoing(&new_config) { outgoing = p; }
+                    if let Ok(d) = sensitive::Detector::new(&new_config.sensitive) { detector = d; }
+                    targets = send_targets(&new_config);
+                    clipboard_for_monitor.lock().unwrap().backend_mut().configure(&new_config.formats);
+                    config_for_monitor = new_config;
+                }
                 
                 // Try to get clipboard data with retry for robustness
                 let clipboard_result = {
@@ -148,7 +347,7 @@ impl ClipboardClient {
                     loop {
                         attempts += 1;
                         let result = {
-                            let mut manager = clipboard_manager_for_monitor.lock().unwrap();
+                            let manager = clipboard_for_monitor.lock().unwrap();
                             manager.get_clipboard_data()
                         };
                         
@@ -165,21 +364,25 @@ impl ClipboardClient {
                 };
                 
                 match clipboard_result {
-                    Ok(clipboard_data) => {
+                    Ok(mut clipboard_data) => {
                         // Use smart change detection to avoid ping-pong loops
                         let content_changed = {
-                            let mut manager = clipboard_manager_for_monitor.lock().unwrap();
-                            manager.has_content_changed(&clipboard_data, false, None)
+                            let mut manager = clipboard_for_monitor.lock().unwrap();
+                            manager.has_content_changed(&clipboard_data)
                         };
                         
                         if content_changed {
+                            clipboard_data.source_app = clipboard_for_monitor.lock().unwrap().backend().source_app();
                             let size_desc = match clipboard_data.content_type.as_str() {
-                                "image" => format!("image data"),
+                                "image" => "image data".to_string(),
                                 "text" => format!("{} chars", clipboard_data.content.len()),
                                 _ => format!("{} chars + rich content", clipboard_data.content.len()),
                             };
                             
                             info!("Local clipboard changed: {}, type: {}", size_desc, clipboard_data.content_type);
+                            if let Some(app) = &clipboard_data.source_app {
+                                info!("  - Copied from {}", app);
+                            }
                             
                             if clipboard_data.html.is_some() {
                                 info!("  - Has HTML content");
@@ -190,30 +393,78 @@ impl ClipboardClient {
                             if clipboard_data.image.is_some() {
                                 info!("  - Has image content");
                             }
+                            if !clipboard_data.extra_formats.is_empty() {
+                                info!("  - Has {} other formats", clipboard_data.extra_formats.len());
+                            }
                             
                             // Mark content as sent before sending to avoid processing it back
                             {
-                                let mut manager = clipboard_manager_for_monitor.lock().unwrap();
+                                let mut manager = clipboard_for_monitor.lock().unwrap();
                                 manager.mark_content_as_sent(&clipboard_data);
                             }
-                            
+                            // A newer copy replaces anything held back for confirmation
+                            if held_for_monitor.lock().unwrap().take().is_some() {
+                                if let Some(tray) = &tray_for_monitor { tray.set_held(None); }
+                            }
+
+                            // Changes made while paused are remembered as seen, so resuming never sends them
+                            if paused_for_monitor.load(std::sync::atomic::Ordering::Relaxed) {
+                                info!("  - Sync paused, not sending");
+                                *last_local_for_monitor.lock().unwrap() = None;
+                                continue;
+                            }
+                            if !config_for_monitor.direction.sends() {
+                                info!("  - Receive-only, not sending");
+                                continue;
+                            }
+
+                            // Checked on what was read, in every format, before the pipeline rewrites or drops any of it.
+                            // Only the detector name is logged, never the content
+                            let concealed = config_for_monitor.sensitive.honor_hints && clipboard_for_monitor.lock().unwrap().backend().is_concealed();
+                            let decision = detector.decide(&clipboard_data, concealed);
+                            let mut clipboard_data = match outgoing.run(clipboard_data) {
+                                Ok(data) => data,
+                                Err(reason) => {
+                                    info!("  - {}, not sending", reason);
+                                    *last_local_for_monitor.lock().unwrap() = None;
+                                    continue;
+                                }
+                            };
+                            match decision {
+                                sensitive::Decision::Send => {}
+                                sensitive::Decision::Skip(reason) => {
+                                    info!("  - Sensitive content ({}), not sending", reason);
+                                    *last_local_for_monitor.lock().unwrap() = None;
+                                    continue;
+                                }
+                                sensitive::Decision::Hold(reason) => {
+                                    clipboard_data.ttl = config_for_monitor.sensitive.ttl;
+                                    info!("  - Sensitive content ({}), held until sent from the tray or with `send-held`", reason);
+                                    *last_local_for_monitor.lock().unwrap() = None;
+                                    *held_for_monitor.lock().unwrap() = Some(clipboard_data);
+                                    if let Some(tray) = &tray_for_monitor { tray.set_held(Some(reason)); }
+                                    continue;
+                                }
+                                sensitive::Decision::Allow(reason) => {
+                                    info!("  - Sensitive content ({}), sending anyway", reason);
+                                    clipboard_data.ttl = config_for_monitor.sensitive.ttl;
+                                }
+                            }
+                            // Kept for explicit "Send to" actions
+                            *last_local_for_monitor.lock().unwrap() = Some(clipboard_data.clone());
+                            // The server echoes what the pipeline produced, so recognise that as ours too
+                            clipboard_for_monitor.lock().unwrap().mark_content_as_sent(&clipboard_data);
+
                             // Send to server via HTTP
                             // Rate-limit posts
                             let now = Instant::now();
                             if let Some(prev) = last_post { if now.duration_since(prev) < MIN_POST_INTERVAL { continue; } }
                             last_post = Some(now);
 
-                            let url = {
-                                let base = url_rx_for_monitor.borrow().clone();
-                                format!("{}/api/clipboard", base)
-                            };
-                            if let Err(e) = http_client
-                                .post(&url)
-                                .json(&clipboard_data)
-                                .send()
-                                .await
-                            {
-                                warn!("Failed to send clipboard to server: {}", e);
+                            for (name, http_client, base) in &targets {
+                                if let Err(e) = post_clipboard(http_client, base, &clipboard_data).await {
+                                    warn!("Failed to send clipboard to '{}': {}", name, e);
+                                }
                             }
                         }
                     }
@@ -233,134 +484,268 @@ impl ClipboardClient {
             }
         });
 
-        // Handle WebSocket messages
-        let clipboard_manager_for_websocket = shared_clipboard_manager.clone();
-        let mut url_rx_for_ws = self.url_rx.clone();
-        let websocket_task = tokio::spawn(async move {
+        // Keep one connection per joined profile, following config changes
+        let mut connections = connections::Connections::new(connections::Context {
+            clipboard: self.clipboard.clone(),
+            config_rx: self.config_rx.clone(),
+            paused: self.paused.clone(),
+            tray: self.tray.clone(),
+        });
+        let mut config_rx = self.config_rx.clone();
+        let supervisor = async move {
             loop {
-                tokio::select! {
-                    maybe_msg = ws_receiver.next() => {
-                        match maybe_msg {
-                            Some(Ok(Message::Text(text))) => {
-                                if let Ok(clipboard_msg) = serde_json::from_str::<ClipboardMessage>(&text) {
-                                    if clipboard_msg.msg_type == "clipboard_update" {
-                                        info!("Received clipboard update from server: {} chars, type: {}",
-                                              clipboard_msg.data.content.len(), clipboard_msg.data.content_type);
-                                        // Check if this is our own content returned from server
-                                        let is_own_content = {
-                                            let manager = clipboard_manager_for_websocket.lock().unwrap();
-                                            manager.is_own_content_returned(&clipboard_msg.data)
-                                        };
-                                        if is_own_content { info!("  - Own content returned, ignoring"); continue; }
-                                        if clipboard_msg.data.html.is_some() { info!("  - Contains HTML content"); }
-                                        if clipboard_msg.data.rtf.is_some() { info!("  - Contains RTF content"); }
-                                        if clipboard_msg.data.image.is_some() { info!("  - Contains image content"); }
-
-                                        let result = {
-                                            let mut manager = clipboard_manager_for_websocket.lock().unwrap();
-                                            manager.set_clipboard_data_from_server(&clipboard_msg.data)
-                                        };
-                                        if let Err(e) = result { error!("Failed to set clipboard: {}", e); }
-                                        else { info!("Successfully updated local clipboard (smart mode)"); }
-                                    }
-                                }
-                            }
-                            Some(Ok(Message::Close(_))) => { info!("WebSocket connection closed by server"); break; }
-                            Some(Err(e)) => { error!("WebSocket error: {}", e); break; }
-                            _ => {}
-                        }
-                    }
-                    _ = url_rx_for_ws.changed() => {
-                        info!("URL changed, reconnecting WebSocket");
-                        break;
-                    }
-                }
+                let config = config_rx.borrow_and_update().clone();
+                connections.sync(&config);
+                if config_rx.changed().await.is_err() { break; }
             }
-        });
+        };
 
-        // Wait for either task to complete
         tokio::select! {
             _ = monitor_task => {
                 info!("Clipboard monitor task ended");
             }
-            _ = websocket_task => {
-                info!("WebSocket task ended");
+            _ = supervisor => {}
+            _ = &mut self.commands => {
+                info!("Quit requested");
             }
         }
+        Ok(())
+    }
+}
 
-        // Mark tray as disconnected before returning
-        if let Some(tray) = &self.tray { tray.set_connected(false); }
+// Profiles that receive every local change, with an HTTP client for each.
+fn send_targets(config: &config::Config) -> Vec<(String, HttpClient, String)> {
+    config
+        .joined()
+        .filter(|(_, profile)| profile.send == config::SendMode::All)
+        .filter_map(|(name, profile)| match http_client(config, profile) {
+            Ok(client) => Some((name.clone(), client, profile.server_url.clone())),
+            Err(e) => {
+                warn!("Not sending to '{}': {}", name, e);
+                None
+            }
+        })
+        .collect()
+}
 
-        Ok(())
+async fn post_clipboard(http_client: &HttpClient, base: &str, data: &ClipboardData) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let url = format!("{}/api/clipboard", base.trim_end_matches('/'));
+    http_client.post(&url).json(data).send().await?.error_for_status()?;
+    Ok(())
+}
+
+fn update_tray_menu(tray: &dyn tray::Tray, config: &config::Config) {
+    tray.set_direction(config.direction);
+    tray.set_profiles(config.profiles.keys().cloned().collect(), &config.active_profile);
+    let manual = config.profiles.iter().filter(|(_, p)| p.send == config::SendMode::Manual);
+    tray.set_send_targets(manual.map(|(name, _)| name.clone()).collect());
+}
+
+// WebSocket endpoint for a server base URL (http → ws, https → wss).
+fn websocket_url(base: &str) -> Result<Url, url::ParseError> {
+    let base = base.trim_end_matches('/');
+    let ws_url = if let Some(rest) = base.strip_prefix("https://") {
+        format!("wss://{}/ws", rest)
+    } else {
+        format!("ws://{}/ws", base.strip_prefix("http://").unwrap_or(base))
+    };
+    Url::parse(&ws_url)
+}
+
+// WebSocket handshake request carrying the same headers as `http_client`.
+fn websocket_request(base: &str, config: &config::Config, profile: &config::Profile) -> Result<tokio_tungstenite::tungstenite::handshake::client::Request, Box<dyn std::error::Error + Send + Sync>> {
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+
+    let mut request = websocket_url(base)?.into_client_request()?;
+    request.headers_mut().extend(request_headers(config, profile)?);
+    Ok(request)
+}
+
+// HTTP client that sends the profile's token, channel and the device name with every request.
+fn http_client(config: &config::Config, profile: &config::Profile) -> Result<HttpClient, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(HttpClient::builder().default_headers(request_headers(config, profile)?).build()?)
+}
+
+fn request_headers(config: &config::Config, profile: &config::Profile) -> Result<reqwest::header::HeaderMap, Box<dyn std::error::Error + Send + Sync>> {
+    use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+
+    let mut headers = HeaderMap::new();
+    if let Some(auth) = profile.authorization() {
+        let mut value = HeaderValue::from_str(&auth)?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
     }
+    headers.insert("x-clipboard-device", HeaderValue::from_str(&config.device_name)?);
+    if let Some(channel) = &profile.channel {
+        headers.insert("x-clipboard-channel", HeaderValue::from_str(channel)?);
+    }
+    Ok(headers)
+}
 
-    async fn run_with_reconnect(&mut self) {
-        let mut reconnect_delay = Duration::from_secs(1);
-        const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
-        
+// Applies edits of the config file to the running client.
+struct ConfigReloader {
+    // Last valid contents of the file, to tell which values an edit actually changed
+    file_config: config::Config,
+    config_tx: tokio::sync::watch::Sender<config::Config>,
+    tray: Option<std::sync::Arc<dyn tray::Tray>>,
+}
+
+impl ConfigReloader {
+    // Re-read the file. `--profile=`/`--url=` overrides stay in effect until the
+    // overridden value changes in the file or `switch_to` selects a profile explicitly.
+    fn reload(&mut self, switch_to: Option<String>) {
+        let loaded = match config::load() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("Ignoring invalid configuration, keeping the previous one: {}", e);
+                if let Some(tray) = &self.tray { tray.set_config_error(Some(e.to_string())); }
+                return;
+            }
+        };
+        if let Some(tray) = &self.tray { tray.set_config_error(None); }
+
+        let current = self.config_tx.borrow().clone();
+        let current_url = current.active().server_url.clone();
+        let mut new_config = loaded.clone();
+        if let Some(name) = switch_to {
+            new_config.active_profile = name;
+        } else if loaded.active_profile == self.file_config.active_profile && loaded.profiles.contains_key(&current.active_profile) {
+            new_config.active_profile = current.active_profile.clone();
+        }
+        let profile = new_config.active_profile.clone();
+        let file_url = |c: &config::Config| c.profiles.get(&profile).map(|p| p.server_url.clone());
+        if profile == current.active_profile && file_url(&loaded) == file_url(&self.file_config) {
+            new_config.active_mut().server_url = current_url;
+        }
+        let new_url = new_config.active().server_url.clone();
+
+        if new_config.log_file != current.log_file {
+            warn!("log_file changes take effect after a restart");
+        }
+        if let Some(tray) = &self.tray {
+            update_tray_menu(tray.as_ref(), &new_config);
+            tray.set_server_url(&new_url);
+        }
+        self.file_config = loaded;
+
+        let changed = self.config_tx.send_if_modified(|cur| {
+            if *cur != new_config { *cur = new_config; true } else { false }
+        });
+        if changed { info!("Configuration reloaded"); }
+    }
+}
+
+// Config file (or defaults), with `--profile=` and `--url=` taking precedence.
+// An invalid file is fatal so it is never silently replaced by defaults on the next save.
+fn load_config(args: &[String]) -> config::Config {
+    let fail = |e: &dyn std::fmt::Display| -> ! {
+        eprintln!("error: {}", e);
+        std::process::exit(2);
+    };
+    let mut config = config::load().unwrap_or_else(|e| fail(&e));
+    if let Some(name) = args.iter().rev().find_map(|a| a.strip_prefix("--profile=")) {
+        if !config.profiles.contains_key(name) {
+            let known: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+            fail(&format!("no profile named '{}' (known: {})", name, known.join(", ")));
+        }
+        config.active_profile = name.to_string();
+    }
+    if let Some(url) = args.iter().rev().find_map(|a| a.strip_prefix("--url=")) {
+        config.active_mut().server_url = url.to_string();
+    }
+    config
+}
+
+// Poll the config file's modification time and ask for a reload when it changes.
+// Polling also catches editors that save by replacing the file.
+fn spawn_config_watcher(cmd_tx: tokio::sync::mpsc::UnboundedSender<Command>) {
+    let Some(path) = config::config_path() else { return };
+    tokio::spawn(async move {
+        let modified = |path: &std::path::Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+        let mut last = modified(&path);
+        let mut ticker = interval(Duration::from_secs(1));
         loop {
-            match self.start().await {
-                Ok(()) => {
-                    info!("Connection ended normally, attempting reconnect...");
-                    reconnect_delay = Duration::from_secs(1); // Reset delay on successful connection
-                }
-                Err(e) => {
-                    error!("Connection failed: {}, retrying in {:?}...", e, reconnect_delay);
-                }
+            ticker.tick().await;
+            let current = modified(&path);
+            if current != last {
+                last = current;
+                debug!("Config file changed: {}", path.display());
+                if cmd_tx.send(Command::ReloadConfig).is_err() { break; }
             }
-            
-            tokio::time::sleep(reconnect_delay).await;
-            
-            // Exponential backoff with maximum delay
-            reconnect_delay = std::cmp::min(reconnect_delay * 2, MAX_RECONNECT_DELAY);
-            
-            info!("Attempting to reconnect to {}", self.url_rx.borrow().as_str());
+        }
+    });
+}
+
+fn init_logging(config: &config::Config) {
+    if let Some(path) = &config.log_file {
+        match std::fs::OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                tracing_subscriber::fmt().with_ansi(false).with_writer(std::sync::Mutex::new(file)).init();
+                return;
+            }
+            Err(e) => eprintln!("warning: cannot open log file {}: {}, logging to stdout", path.display(), e),
         }
     }
+    tracing_subscriber::fmt::init();
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    let args = std::env::args().collect::<Vec<_>>();
 
+    // One-shot subcommands log to stderr so stdout stays clean for pipelines
+    let cli_command = if args.iter().any(|a| a == "--settings") {
+        None
+    } else {
+        match cli::parse(&args) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                eprintln!("error: {}\n\n{}", e, cli::USAGE);
+                std::process::exit(2);
+            }
+        }
+    };
 
-    // Если запущено в режиме настроек отдельным процессом, просто показать окно и выйти
-    {
-        let args = std::env::args().collect::<Vec<_>>();
-        if args.iter().any(|a| a == "--settings") {
-            let mut url = "http://127.0.0.1:8080".to_string();
-            if let Ok(env_url) = std::env::var("CLIPBOARD_SERVER_URL") { url = env_url; }
-            for a in &args { if let Some(s) = a.strip_prefix("--url=") { url = s.to_string(); } }
+    // Если запущено в режиме настроек отдельным процессом, просто показать окно и выйти.
+    // The window loads the config itself so it can show (and fix) an invalid file.
+    if args.iter().any(|a| a == "--settings") {
+        tracing_subscriber::fmt::init();
+        #[cfg(feature = "gui")]
+        {
             let connected = args.iter().any(|a| a == "--connected");
-            if let Some(new_url) = crate::settings::run_settings_ui(url, connected) { println!("{}", new_url); }
+            if crate::settings::run_settings_ui(connected) { println!("saved"); }
             return Ok(());
         }
+        #[cfg(not(feature = "gui"))]
+        {
+            eprintln!("error: this build has no settings window (built without the `gui` feature)");
+            std::process::exit(2);
+        }
     }
 
-    // Load config or env
-    let server_url = config::load_server_url().unwrap_or_else(|| {
-        std::env::var("CLIPBOARD_SERVER_URL").unwrap_or_else(|_| "http://127.0.0.1:8080".to_string())
-    });
+    let config = load_config(&args);
+    if let Some(cmd) = cli_command {
+        tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+        if let Err(e) = cli::run(cmd, &config).await {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
 
-    info!("Starting clipboard client daemon, connecting to: {}", server_url);
+    // Initialize tracing
+    init_logging(&config);
 
-    let mut client = ClipboardClient::new(server_url.clone())?;
+    info!("Starting clipboard client daemon, profile '{}', connecting to: {}", config.active_profile, config.active().server_url);
 
-    // Persist URL changes
-    let mut url_rx_for_persist = client.url_rx.clone();
-    tokio::spawn(async move {
-        let mut last = url_rx_for_persist.borrow().clone();
-        loop {
-            if url_rx_for_persist.changed().await.is_err() { break; }
-            let current = url_rx_for_persist.borrow().clone();
-            if current != last { let _ = config::save_server_url(&current); last = current.clone(); }
-        }
-    });
+    let headless = args.iter().any(|a| a == "--headless");
+    let mut client = ClipboardClient::new(config, headless)?;
 
     // Handle graceful shutdown
     tokio::select! {
-        _ = client.run_with_reconnect() => { info!("Client reconnection loop ended"); }
+        result = client.run() => {
+            if let Err(e) = result { error!("Client stopped: {}", e); }
+        }
         _ = tokio::signal::ctrl_c() => { info!("Received Ctrl+C, shutting down..."); }
     }
 
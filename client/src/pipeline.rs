@@ -0,0 +1,410 @@
+// Filter and transform stages applied to clipboard content on its way out (between
+// reading the local clipboard and uploading) and on its way in (before it is written
+// to the local clipboard).
+//
+// Each direction starts with the built-in stages: format conversion (see convert.rs) and
+// those derived from `[formats]`, `[limits]`, outgoing only `[apps]` and `[filters]`, and
+// incoming only `[html]`. They are followed by the `[[pipeline.outgoing]]` /
+// `[[pipeline.incoming]]` stages from the config file in the order they are listed.
+
+use regex::Regex;
+
+use crate::config::{Apps, Config, Formats, Html, Limits, Stage};
+use crate::ClipboardData;
+
+enum Step {
+    Apps(Apps),
+    SanitizeHtml(Html),
+    // Plain text from HTML or RTF when it is missing or only the HTML source
+    FillText,
+    // Every missing representation, so the receiving platform gets its best one
+    Complete,
+    Formats(Formats),
+    MaxSize(Limits),
+    TrimWhitespace,
+    PlainTextOnly,
+    Ignore(Regex),
+    Replace(Regex, String),
+}
+
+// Why a stage dropped the content, for the log.
+pub type DropReason = String;
+
+pub struct Pipeline {
+    steps: Vec<Step>,
+}
+
+impl Pipeline {
+    // Stages for local changes before they are sent.
+    // Errors name the offending config key and why it is invalid.
+    pub fn outgoing(config: &Config) -> Result<Self, (String, String)> {
+        let mut steps = vec![Step::Apps(config.apps.clone()), Step::FillText];
+        for pattern in &config.filters.ignore_patterns {
+            steps.push(Step::Ignore(compile("filters.ignore_patterns", pattern)?));
+        }
+        steps.push(Step::Formats(config.formats.clone()));
+        steps.push(Step::MaxSize(config.limits.clone()));
+        for (i, stage) in config.pipeline.outgoing.iter().enumerate() {
+            steps.push(Step::new(stage, &format!("pipeline.outgoing[{}]", i))?);
+        }
+        Ok(Self { steps })
+    }
+
+    // Stages for updates from a server before they are applied.
+    pub fn incoming(config: &Config) -> Result<Self, (String, String)> {
+        let mut steps = Vec::new();
+        if config.html.sanitize {
+            steps.push(Step::SanitizeHtml(config.html.clone()));
+        }
+        steps.push(Step::Complete);
+        steps.push(Step::Formats(config.formats.clone()));
+        steps.push(Step::MaxSize(config.limits.clone()));
+        for (i, stage) in config.pipeline.incoming.iter().enumerate() {
+            steps.push(Step::new(stage, &format!("pipeline.incoming[{}]", i))?);
+        }
+        Ok(Self { steps })
+    }
+
+    // Run every stage in order; the first one that drops the content stops the rest.
+    pub fn run(&self, mut data: ClipboardData) -> Result<ClipboardData, DropReason> {
+        for step in &self.steps {
+            step.apply(&mut data)?;
+        }
+        Ok(data)
+    }
+}
+
+fn compile(field: &str, pattern: &str) -> Result<Regex, (String, String)> {
+    Regex::new(pattern).map_err(|e| (field.to_string(), format!("bad regex '{}': {}", pattern, e)))
+}
+
+impl Step {
+    fn new(stage: &Stage, field: &str) -> Result<Self, (String, String)> {
+        Ok(match stage {
+            Stage::TrimWhitespace => Step::TrimWhitespace,
+            Stage::PlainTextOnly => Step::PlainTextOnly,
+            Stage::MaxSize { bytes } => Step::MaxSize(Limits { max_content_bytes: *bytes }),
+            Stage::Ignore { pattern } => Step::Ignore(compile(&format!("{}.pattern", field), pattern)?),
+            Stage::Replace { pattern, replacement } => {
+                Step::Replace(compile(&format!("{}.pattern", field), pattern)?, replacement.clone())
+            }
+        })
+    }
+
+    fn apply(&self, data: &mut ClipboardData) -> Result<(), DropReason> {
+        match self {
+            Step::Apps(apps) => {
+                if !apps.allows(data.source_app.as_deref()) {
+                    return Err(match &data.source_app {
+                        Some(app) => format!("copied from {}", app),
+                        None => "copied from an unknown application".into(),
+                    });
+                }
+            }
+            Step::SanitizeHtml(html) => {
+                if let Some(dirty) = &data.html {
+                    let clean = sanitize(html, dirty);
+                    // Nothing left worth pasting as HTML; the plain text still is
+                    data.html = (!clean.trim().is_empty()).then_some(clean);
+                }
+            }
+            Step::FillText => crate::convert::fill_text(data),
+            Step::Complete => crate::convert::complete(data),
+            Step::Formats(formats) => {
+                if !formats.apply(data) {
+                    return Err("no enabled formats left".into());
+                }
+            }
+            Step::MaxSize(limits) => {
+                if !limits.allows(data) {
+                    return Err(format!("larger than {} bytes", limits.max_content_bytes));
+                }
+            }
+            Step::TrimWhitespace => {
+                let trimmed: Vec<&str> = data.content.lines().map(str::trim_end).collect();
+                data.content = trimmed.join("\n").trim_end().to_string();
+            }
+            Step::PlainTextOnly => {
+                if data.content.is_empty() {
+                    return Err("no plain text to send".into());
+                }
+                data.html = None;
+                data.rtf = None;
+                data.image = None;
+                data.extra_formats.clear();
+                data.content_type = "text".to_string();
+            }
+            Step::Ignore(re) => {
+                if re.is_match(&data.content) {
+                    return Err(format!("matches ignore pattern '{}'", re.as_str()));
+                }
+            }
+            Step::Replace(re, replacement) => {
+                data.content = re.replace_all(&data.content, replacement.as_str()).into_owned();
+                // A match split by markup (`intra<b>net</b>`, `{\b intra}net`) survives rewriting the
+                // source; such a representation is dropped and the receiver derives it from the rest
+                let html = data.html.as_ref().map(|html| re.replace_all(html, replacement.as_str()).into_owned());
+                data.html = html.filter(|html| !re.is_match(&crate::convert::html_to_text(html)));
+                let rtf = data.rtf.as_ref().map(|rtf| re.replace_all(rtf, replacement.as_str()).into_owned());
+                data.rtf = rtf.filter(|rtf| !re.is_match(&crate::convert::rtf_to_text(rtf)));
+                data.update_content_type();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sanitize(config: &Html, html: &str) -> String {
+    let mut builder = ammonia::Builder::default();
+    if !config.tags.is_empty() {
+        builder.tags(config.tags.iter().map(String::as_str).collect());
+    }
+    if !config.attributes.is_empty() {
+        builder.generic_attributes(config.attributes.iter().map(String::as_str).collect());
+        // ammonia sets rel on links itself and refuses an allowlisted rel alongside that
+        if config.attributes.iter().any(|a| a.eq_ignore_ascii_case("rel")) {
+            builder.link_rel(None);
+        }
+    }
+    if !config.remote_images {
+        builder.attribute_filter(|element, attribute, value| {
+            let lower = value.trim_start().to_ascii_lowercase();
+            let remote = lower.starts_with("http:") || lower.starts_with("https:") || lower.starts_with("//");
+            if element == "img" && attribute == "src" && remote { None } else { Some(value.into()) }
+        });
+    }
+    builder.clean(html).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn html(content: &str, html: &str) -> ClipboardData {
+        ClipboardData { html: Some(html.to_string()), content_type: "html".to_string(), ..ClipboardData::text(content) }
+    }
+
+    fn outgoing(stages: Vec<Stage>) -> Pipeline {
+        let mut config = Config::default();
+        config.pipeline.outgoing = stages;
+        Pipeline::outgoing(&config).unwrap()
+    }
+
+    #[test]
+    fn trim_whitespace_strips_line_ends_and_trailing_lines() {
+        let out = outgoing(vec![Stage::TrimWhitespace]).run(ClipboardData::text("a  \n\tb\t\n\n")).unwrap();
+        assert_eq!(out.content, "a\n\tb");
+    }
+
+    #[test]
+    fn plain_text_only_drops_rich_formats() {
+        let out = outgoing(vec![Stage::PlainTextOnly]).run(html("hi", "<b>hi</b>")).unwrap();
+        assert_eq!(out.html, None);
+        assert_eq!(out.content_type, "text");
+        assert_eq!(out.content, "hi");
+
+        // Passthrough MIME types are rich formats too
+        let mut config = Config::default();
+        config.formats.mime_types = vec!["image/svg+xml".into()];
+        config.pipeline.outgoing = vec![Stage::PlainTextOnly];
+        let svg = ClipboardData { extra_formats: [("image/svg+xml".to_string(), "PHN2Zy8+".to_string())].into(), ..ClipboardData::text("hi") };
+        assert!(Pipeline::outgoing(&config).unwrap().run(svg).unwrap().extra_formats.is_empty());
+    }
+
+    #[test]
+    fn plain_text_only_drops_image_without_text() {
+        let image = ClipboardData { image: Some("iVBOR".into()), content_type: "image".into(), ..ClipboardData::text("") };
+        assert!(outgoing(vec![Stage::PlainTextOnly]).run(image).is_err());
+    }
+
+    #[test]
+    fn max_size_counts_all_formats() {
+        let pipeline = outgoing(vec![Stage::MaxSize { bytes: 10 }]);
+        assert!(pipeline.run(ClipboardData::text("0123456789")).is_ok());
+        assert!(pipeline.run(html("01234", "<p>012</p>")).is_err());
+    }
+
+    #[test]
+    fn ignore_drops_matching_content() {
+        let pipeline = outgoing(vec![Stage::Ignore { pattern: "^otpauth://".into() }]);
+        assert!(pipeline.run(ClipboardData::text("otpauth://totp/x")).is_err());
+        assert!(pipeline.run(ClipboardData::text("see otpauth://totp/x")).is_ok());
+    }
+
+    #[test]
+    fn replace_rewrites_text_and_html() {
+        let pipeline = outgoing(vec![Stage::Replace {
+            pattern: r"https?://intranet\.corp/".into(),
+            replacement: "https://vpn.example.com/".into(),
+        }]);
+        let out = pipeline.run(html("http://intranet.corp/a", "<a href=\"https://intranet.corp/a\">a</a>")).unwrap();
+        assert_eq!(out.content, "https://vpn.example.com/a");
+        assert_eq!(out.html.as_deref(), Some("<a href=\"https://vpn.example.com/a\">a</a>"));
+    }
+
+    #[test]
+    fn replace_rewrites_rtf() {
+        let pipeline = outgoing(vec![Stage::Replace { pattern: r"intranet\.corp".into(), replacement: "vpn.example.com".into() }]);
+        let rtf = |rtf: &str| ClipboardData { rtf: Some(rtf.to_string()), content_type: "rtf".to_string(), ..ClipboardData::text("see intranet.corp") };
+        let out = pipeline.run(rtf("{\\rtf1\\ansi see intranet.corp}")).unwrap();
+        assert_eq!(out.content, "see vpn.example.com");
+        assert_eq!(out.rtf.as_deref(), Some("{\\rtf1\\ansi see vpn.example.com}"));
+
+        // Split by a control word, so rewriting the source misses it
+        let out = pipeline.run(rtf("{\\rtf1\\ansi see {\\b intra}net.corp}")).unwrap();
+        assert_eq!(out.rtf, None);
+        assert_eq!(out.content_type, "text");
+        assert_eq!(out.content, "see vpn.example.com");
+    }
+
+    #[test]
+    fn replace_supports_capture_groups() {
+        let pipeline = outgoing(vec![Stage::Replace { pattern: r"JIRA-(\d+)".into(), replacement: "https://jira/$1".into() }]);
+        assert_eq!(pipeline.run(ClipboardData::text("see JIRA-42")).unwrap().content, "see https://jira/42");
+    }
+
+    #[test]
+    fn stages_run_in_order() {
+        // Trimming first makes the anchored pattern match
+        let pipeline = outgoing(vec![Stage::TrimWhitespace, Stage::Ignore { pattern: "^secret$".into() }]);
+        assert!(pipeline.run(ClipboardData::text("secret   ")).is_err());
+        let pipeline = outgoing(vec![Stage::Ignore { pattern: "^secret$".into() }, Stage::TrimWhitespace]);
+        assert!(pipeline.run(ClipboardData::text("secret   ")).is_ok());
+    }
+
+    #[test]
+    fn builtin_stages_come_from_formats_limits_and_filters() {
+        let mut config = Config::default();
+        config.formats.html = false;
+        config.limits.max_content_bytes = 4;
+        config.filters.ignore_patterns = vec!["^x".into()];
+        let pipeline = Pipeline::outgoing(&config).unwrap();
+        assert_eq!(pipeline.run(html("abc", "<b>abc</b>")).unwrap().html, None);
+        assert!(pipeline.run(ClipboardData::text("abcde")).is_err());
+        assert!(pipeline.run(ClipboardData::text("xy")).is_err());
+        // Incoming updates are not subject to the ignore patterns
+        assert!(Pipeline::incoming(&config).unwrap().run(ClipboardData::text("xy")).is_ok());
+    }
+
+    #[test]
+    fn apps_rules_match_the_source_application() {
+        let from = |app: Option<&str>| ClipboardData { source_app: app.map(String::from), ..ClipboardData::text("x") };
+        let mut config = Config::default();
+        config.apps.exclude = vec!["KeePassXC".into()];
+        let pipeline = Pipeline::outgoing(&config).unwrap();
+        assert!(pipeline.run(from(Some("keepassxc"))).is_err());
+        assert!(pipeline.run(from(Some("firefox"))).is_ok());
+        assert!(pipeline.run(from(None)).is_ok());
+
+        config.apps.include = vec!["firefox".into()];
+        config.apps.allow_unknown = false;
+        let pipeline = Pipeline::outgoing(&config).unwrap();
+        assert!(pipeline.run(from(Some("Firefox"))).is_ok());
+        assert!(pipeline.run(from(Some("kitty"))).is_err());
+        assert!(pipeline.run(from(None)).is_err());
+        // Incoming updates carry the sender's application, which is not filtered here
+        assert!(Pipeline::incoming(&config).unwrap().run(from(Some("kitty"))).is_ok());
+    }
+
+    fn incoming(config: &Config, data: ClipboardData) -> ClipboardData {
+        Pipeline::incoming(config).unwrap().run(data).unwrap()
+    }
+
+    #[test]
+    fn sanitize_removes_scripts_handlers_and_javascript_links() {
+        let dirty = r#"<p onclick="steal()">hi<script>steal()</script> <a href="javascript:steal()">x</a> <a href="https://example.com">y</a></p>"#;
+        let out = incoming(&Config::default(), html("hi x y", dirty)).html.unwrap();
+        assert!(!out.contains("script") && !out.contains("onclick") && !out.contains("javascript"), "{}", out);
+        assert!(out.contains(r#"href="https://example.com""#), "{}", out);
+    }
+
+    #[test]
+    fn sanitize_strips_remote_images_unless_allowed() {
+        let dirty = r#"<b>hi</b><img src="https://tracker.example/p.gif" alt="pixel">"#;
+        let mut config = Config::default();
+        assert!(!incoming(&config, html("hi", dirty)).html.unwrap().contains("tracker"));
+        config.html.remote_images = true;
+        assert!(incoming(&config, html("hi", dirty)).html.unwrap().contains("tracker"));
+    }
+
+    #[test]
+    fn sanitize_uses_configured_allowlists() {
+        let mut config = Config::default();
+        config.html.tags = vec!["b".into()];
+        config.html.attributes = vec!["class".into()];
+        let out = incoming(&config, html("a b", r#"<i>a</i> <b class="x" title="t">b</b>"#)).html.unwrap();
+        assert_eq!(out, r#"a <b class="x">b</b>"#);
+    }
+
+    #[test]
+    fn sanitize_keeps_rel_when_allowed() {
+        let mut config = Config::default();
+        config.html.attributes = vec!["href".into(), "rel".into()];
+        let out = incoming(&config, html("x", r#"<a href="https://example.com" rel="nofollow">x</a>"#)).html.unwrap();
+        assert_eq!(out, r#"<a href="https://example.com" rel="nofollow">x</a>"#);
+    }
+
+    #[test]
+    fn sanitize_drops_html_that_is_left_empty() {
+        let out = incoming(&Config::default(), html("", "<script>steal()</script>"));
+        assert_eq!(out.html, None);
+    }
+
+    #[test]
+    fn sanitize_can_be_disabled() {
+        let mut config = Config::default();
+        config.html.sanitize = false;
+        let dirty = "<b onclick=\"x()\">hi</b>";
+        assert_eq!(incoming(&config, html("hi", dirty)).html.as_deref(), Some(dirty));
+    }
+
+    #[test]
+    fn incoming_html_gets_plain_text_and_rtf() {
+        let out = incoming(&Config::default(), html("<b>hi</b>", "<b>hi</b>"));
+        assert_eq!(out.content, "hi");
+        assert!(out.rtf.is_some());
+        assert_eq!(out.content_type, "mixed");
+    }
+
+    #[test]
+    fn derived_formats_respect_disabled_formats() {
+        let mut config = Config::default();
+        config.formats.rtf = false;
+        let out = incoming(&config, html("hi", "<b>hi</b>"));
+        assert_eq!(out.rtf, None);
+        assert_eq!(out.content_type, "html");
+    }
+
+    #[test]
+    fn outgoing_replaces_html_source_in_plain_text() {
+        let out = outgoing(vec![]).run(html("<p>a <i>b</i></p>", "<p>a <i>b</i></p>")).unwrap();
+        assert_eq!(out.content, "a b");
+        assert_eq!(out.rtf, None);
+    }
+
+    #[test]
+    fn extra_formats_follow_the_mime_type_allowlist() {
+        let svg = "PHN2Zy8+".to_string(); // "<svg/>"
+        let data = || ClipboardData {
+            extra_formats: [("image/svg+xml".to_string(), svg.clone()), ("application/x-secret".to_string(), svg.clone())].into(),
+            ..ClipboardData::text("x")
+        };
+        assert!(Pipeline::outgoing(&Config::default()).unwrap().run(data()).unwrap().extra_formats.is_empty());
+
+        let mut config = Config::default();
+        config.formats.mime_types = vec!["image/*".into()];
+        let out = Pipeline::incoming(&config).unwrap().run(data()).unwrap();
+        assert_eq!(out.extra_formats.keys().collect::<Vec<_>>(), ["image/svg+xml"]);
+
+        config.formats.max_mime_bytes = 3;
+        assert!(Pipeline::outgoing(&config).unwrap().run(data()).unwrap().extra_formats.is_empty());
+    }
+
+    #[test]
+    fn invalid_regex_names_the_stage() {
+        let mut config = Config::default();
+        config.pipeline.incoming = vec![Stage::TrimWhitespace, Stage::Ignore { pattern: "(".into() }];
+        let (field, _) = Pipeline::incoming(&config).err().unwrap();
+        assert_eq!(field, "pipeline.incoming[1].pattern");
+    }
+}
@@ -1,37 +1,116 @@
 use eframe::egui;
 use std::sync::{Arc, Mutex};
 
-// На Linux запускаем отдельный процесс с флагом --settings и передаем URL через аргументы.
-#[cfg(target_os = "linux")]
-pub fn open_settings_blocking(current_url: String, connected: bool) -> Option<String> {
-    let exe = std::env::current_exe().ok()?;
+use crate::config;
+
+// На Linux запускаем отдельный процесс с флагом --settings; он сам сохраняет конфиг.
+// Returns true when the user saved changes.
+#[cfg(all(target_os = "linux", feature = "tray"))]
+pub fn open_settings_blocking(connected: bool) -> bool {
+    let Ok(exe) = std::env::current_exe() else { return false };
     let status_flag = if connected { "--connected" } else { "--disconnected" };
-    let output = std::process::Command::new(exe)
+    let output = match std::process::Command::new(exe)
         .arg("--settings")
-        .arg(format!("--url={}", current_url))
         .arg(status_flag)
         .output()
-        .ok()?;
-    if !output.status.success() { return None; }
-    let s = String::from_utf8_lossy(&output.stdout).trim().to_string();
-    if s.is_empty() { None } else { Some(s) }
+    {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+    output.status.success() && String::from_utf8_lossy(&output.stdout).trim() == "saved"
 }
 
 // Non-Linux or direct UI path: run UI in-process
-#[cfg(not(target_os = "linux"))]
-pub fn open_settings_blocking(current_url: String, connected: bool) -> Option<String> {
-    run_settings_ui(current_url, connected)
+#[cfg(all(not(target_os = "linux"), feature = "tray"))]
+pub fn open_settings_blocking(connected: bool) -> bool {
+    run_settings_ui(connected)
 }
 
-pub fn run_settings_ui(current_url: String, connected: bool) -> Option<String> {
+// Edit the server profiles and sync direction in the config file. Returns true when they were saved.
+pub fn run_settings_ui(connected: bool) -> bool {
+    // Text fields of the profile being edited; empty optional fields mean "not set"
+    #[derive(Default)]
+    struct ProfileForm {
+        name: String,
+        server_url: String,
+        auth_token: String,
+        channel: String,
+    }
+
+    impl ProfileForm {
+        fn new(name: &str, profile: &config::Profile) -> Self {
+            let text = |v: &Option<String>| v.clone().unwrap_or_default();
+            Self {
+                name: name.to_string(),
+                server_url: profile.server_url.clone(),
+                auth_token: text(&profile.auth_token),
+                channel: text(&profile.channel),
+            }
+        }
+
+        fn apply(&self, profile: &mut config::Profile) {
+            let opt = |v: &str| Some(v.trim().to_string()).filter(|v| !v.is_empty());
+            profile.server_url = self.server_url.trim().to_string();
+            profile.auth_token = opt(&self.auth_token);
+            profile.channel = opt(&self.channel);
+        }
+    }
+
     struct App {
-        url_input: String,
+        // Working copy; written back to the file only on Save
+        config: Option<config::Config>,
+        selected: String,
+        form: ProfileForm,
         connected: bool,
-        test_result: Option<String>,
-        saved_url: Arc<Mutex<Option<String>>>,
+        message: Option<String>,
+        saved: Arc<Mutex<bool>>,
         did_setup: bool,
     }
 
+    impl App {
+        // Store the form into the working copy, renaming the profile if needed.
+        fn commit_form(&mut self) -> Result<(), String> {
+            let Some(config) = self.config.as_mut() else { return Ok(()) };
+            let name = self.form.name.trim().to_string();
+            if name.is_empty() {
+                return Err("Profile name must not be empty".into());
+            }
+            if name != self.selected && config.profiles.contains_key(&name) {
+                return Err(format!("A profile named '{}' already exists", name));
+            }
+            let mut profile = config.profiles.remove(&self.selected).unwrap_or_default();
+            self.form.apply(&mut profile);
+            config.profiles.insert(name.clone(), profile);
+            if config.active_profile == self.selected {
+                config.active_profile = name.clone();
+            }
+            self.selected = name;
+            Ok(())
+        }
+
+        fn select(&mut self, name: String) {
+            if let Some(profile) = self.config.as_ref().and_then(|c| c.profiles.get(&name)) {
+                self.form = ProfileForm::new(&name, profile);
+                self.selected = name;
+            }
+        }
+
+        fn save(&mut self) -> Result<(), String> {
+            self.commit_form()?;
+            let Some(edited) = self.config.clone() else { return Err("Nothing to save".into()) };
+            let path = config::config_path().ok_or("No configuration directory")?;
+            edited.validate(&path).map_err(|e| e.to_string())?;
+            // Only the settings shown here are written; other keys edited meanwhile are kept
+            config::update(|c| {
+                c.profiles = edited.profiles;
+                c.active_profile = edited.active_profile;
+                c.direction = edited.direction;
+            })
+            .map(|_| ())
+            .map_err(|e| e.to_string())
+        }
+    }
+
     impl eframe::App for App {
 fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
             if !self.did_setup {
@@ -61,44 +140,125 @@ fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
                 let status = if self.connected { "Connected" } else { "Disconnected" };
                 ui.label(format!("Status: {}", status));
 
+                let Some(config) = self.config.as_mut() else {
+                    if let Some(msg) = &self.message { ui.label(msg); }
+                    if ui.button("Close").clicked() { close(ctx); }
+                    return;
+                };
+
                 ui.horizontal(|ui| {
+                    ui.label("Sync direction:");
+                    egui::ComboBox::from_id_source("direction")
+                        .selected_text(config.direction.label())
+                        .show_ui(ui, |ui| {
+                            for direction in config::Direction::ALL {
+                                ui.selectable_value(&mut config.direction, direction, direction.label());
+                            }
+                        });
+                });
+
+                // Profile picker
+                let names: Vec<String> = config.profiles.keys().cloned().collect();
+                let active = config.active_profile.clone();
+                let mut choice = self.selected.clone();
+                ui.horizontal(|ui| {
+                    ui.label("Profile:");
+                    egui::ComboBox::from_id_source("profile")
+                        .selected_text(&self.selected)
+                        .show_ui(ui, |ui| {
+                            for name in &names {
+                                let label = if *name == active { format!("{} (active)", name) } else { name.clone() };
+                                ui.selectable_value(&mut choice, name.clone(), label);
+                            }
+                        });
+                    if ui.button("New").clicked() {
+                        match self.commit_form() {
+                            Ok(()) => {
+                                let name = (1..).map(|n| format!("profile {}", n)).find(|n| !names.contains(n)).unwrap_or_default();
+                                if let Some(config) = self.config.as_mut() {
+                                    config.profiles.insert(name.clone(), config::Profile::default());
+                                }
+                                choice = name;
+                            }
+                            Err(e) => self.message = Some(e),
+                        }
+                    }
+                    if ui.add_enabled(names.len() > 1, egui::Button::new("Delete")).clicked() {
+                        if let Some(config) = self.config.as_mut() {
+                            config.profiles.remove(&self.selected);
+                            let first = config.profiles.keys().next().cloned().unwrap_or_default();
+                            if config.active_profile == self.selected { config.active_profile = first.clone(); }
+                            self.selected.clear();
+                            choice = first;
+                        }
+                    }
+                });
+                if choice != self.selected {
+                    // Keep edits of the profile being left; a deleted profile has none
+                    let committed = if self.selected.is_empty() { Ok(()) } else { self.commit_form() };
+                    match committed {
+                        Ok(()) => self.select(choice),
+                        Err(e) => self.message = Some(e),
+                    }
+                }
+
+                egui::Grid::new("profile_fields").num_columns(2).show(ui, |ui| {
+                    ui.label("Name:");
+                    ui.text_edit_singleline(&mut self.form.name);
+                    ui.end_row();
                     ui.label("Server URL:");
-                    let te = egui::TextEdit::singleline(&mut self.url_input).hint_text("http://127.0.0.1:8080");
-                    ui.add(te);
+                    ui.add(egui::TextEdit::singleline(&mut self.form.server_url).hint_text("http://127.0.0.1:8080"));
+                    ui.end_row();
+                    ui.label("Auth token:");
+                    ui.add(egui::TextEdit::singleline(&mut self.form.auth_token).password(true));
+                    ui.end_row();
+                    ui.label("Channel:");
+                    ui.add(egui::TextEdit::singleline(&mut self.form.channel).hint_text("default"));
+                    ui.end_row();
                 });
 
+                let is_active = self.config.as_ref().is_some_and(|c| c.active_profile == self.selected);
+                let mut make_active = is_active;
+                if ui.checkbox(&mut make_active, "Sync with this profile").changed() && make_active {
+                    if let Some(config) = self.config.as_mut() { config.active_profile = self.selected.clone(); }
+                }
+
                 ui.horizontal(|ui| {
                     if ui.button("Test connection").clicked() {
-                        let url = self.url_input.clone();
-                        let res = test_connect(&url);
-                        self.test_result = Some(res);
+                        let token = Some(self.form.auth_token.trim()).filter(|t| !t.is_empty());
+                        self.message = Some(test_connect(self.form.server_url.trim(), token));
                     }
                     if ui.button("Save").clicked() {
-                        *self.saved_url.lock().unwrap() = Some(self.url_input.clone());
-                        let ctx2 = ctx.clone();
-                        std::thread::spawn(move || {
-                            // Defer close to avoid deadlock in the same update frame
-                            std::thread::sleep(std::time::Duration::from_millis(10));
-                            ctx2.send_viewport_cmd_to(egui::ViewportId::ROOT, egui::ViewportCommand::Close);
-                        });
+                        match self.save() {
+                            Ok(()) => {
+                                *self.saved.lock().unwrap() = true;
+                                close(ctx);
+                            }
+                            Err(e) => self.message = Some(format!("Not saved: {}", e)),
+                        }
                     }
                     if ui.button("Close").clicked() {
-                        let ctx2 = ctx.clone();
-                        std::thread::spawn(move || {
-                            std::thread::sleep(std::time::Duration::from_millis(10));
-                            ctx2.send_viewport_cmd_to(egui::ViewportId::ROOT, egui::ViewportCommand::Close);
-                        });
+                        close(ctx);
                     }
                 });
 
-                if let Some(msg) = &self.test_result {
+                if let Some(msg) = &self.message {
                     ui.label(msg);
                 }
             });
         }
     }
 
-    fn test_connect(base: &str) -> String {
+    fn close(ctx: &egui::Context) {
+        let ctx2 = ctx.clone();
+        std::thread::spawn(move || {
+            // Defer close to avoid deadlock in the same update frame
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            ctx2.send_viewport_cmd_to(egui::ViewportId::ROOT, egui::ViewportCommand::Close);
+        });
+    }
+
+    fn test_connect(base: &str, token: Option<&str>) -> String {
         let url = format!("{}/api/clipboard", base.trim_end_matches('/'));
         let client = match reqwest::blocking::Client::builder()
             .timeout(std::time::Duration::from_secs(3))
@@ -106,14 +266,25 @@ fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
             Ok(c) => c,
             Err(e) => return format!("Error: {}", e),
         };
-        match client.get(url).send() {
+        let mut request = client.get(url);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        match request.send() {
             Ok(resp) => format!("HTTP {}", resp.status()),
             Err(e) => format!("Error: {}", e),
         }
     }
 
-    let saved_url = Arc::new(Mutex::new(None));
-    let app = App { url_input: current_url.clone(), connected, test_result: None, saved_url: saved_url.clone(), did_setup: false };
+    let saved = Arc::new(Mutex::new(false));
+    let (config, message) = match config::load() {
+        Ok(config) => (Some(config), None),
+        Err(e) => (None, Some(format!("Fix the config file first: {}", e))),
+    };
+    let mut app = App { config, selected: String::new(), form: ProfileForm::default(), connected, message, saved: saved.clone(), did_setup: false };
+    if let Some(active) = app.config.as_ref().map(|c| c.active_profile.clone()) {
+        app.select(active);
+    }
 
     // Default options; eframe/winit handle platform specifics
     let native_options = eframe::NativeOptions::default();
@@ -124,6 +295,6 @@ fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         Box::new(|_cc| Ok(Box::new(app))),
     );
 
-    Arc::try_unwrap(saved_url).ok().and_then(|m| m.into_inner().ok()).and_then(|v| v)
+    let saved = *saved.lock().unwrap();
+    saved
 }
-
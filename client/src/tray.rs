@@ -1,19 +1,57 @@
 // Linux system tray integration using ksni (StatusNotifier)
-// Provides a tray icon with a status label (disabled) and an Exit action.
+// Provides a tray icon with a status label (disabled), pause/resume and an Exit action.
+// The `Tray` trait is platform-independent; without the `tray` feature or a
+// session bus the client simply runs without an icon.
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex};
 
-pub trait Tray: Send + Sync { fn set_connected(&self, connected: bool); }
+pub trait Tray: Send + Sync {
+    fn set_connected(&self, connected: bool);
+    fn set_server_url(&self, url: &str);
+    // Profile names for the switcher and the one in use
+    fn set_profiles(&self, profiles: Vec<String>, active: &str);
+    // Profiles offered in the "Send clipboard to" menu (those with `send = "manual"`)
+    fn set_send_targets(&self, profiles: Vec<String>);
+    // Show (or clear) a problem with the config file that the user should fix
+    fn set_config_error(&self, error: Option<String>);
+    // Switch between the paused and the normal icon and menu
+    fn set_paused(&self, paused: bool);
+    fn set_direction(&self, direction: crate::config::Direction);
+    // Offer (or withdraw) sending content held back as sensitive; `reason` names the detector
+    fn set_held(&self, reason: Option<String>);
+}
+
+// Start the platform tray, or return None when it is compiled out or unavailable.
+#[allow(unused_variables)]
+pub fn start(server_url: String, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> Option<Box<dyn Tray>> {
+    #[cfg(all(target_os = "linux", feature = "tray"))]
+    return start_tray(server_url, cmd_tx).map(|t| Box::new(t) as Box<dyn Tray>);
+    #[cfg(all(target_os = "windows", feature = "tray"))]
+    return crate::tray_win::start_tray(server_url, cmd_tx).map(|t| Box::new(t) as Box<dyn Tray>);
+    #[cfg(not(all(any(target_os = "linux", target_os = "windows"), feature = "tray")))]
+    {
+        tracing::info!("Built without tray support");
+        None
+    }
+}
+
+// Entries of the "Pause sync" menu and when each one resumes by itself
+#[cfg(feature = "tray")]
+pub const PAUSE_CHOICES: [(&str, Option<std::time::Duration>); 3] = [
+    ("For 15 minutes", Some(std::time::Duration::from_secs(15 * 60))),
+    ("For 1 hour", Some(std::time::Duration::from_secs(60 * 60))),
+    ("Until resumed", None),
+];
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 pub struct TrayController {
     connected: Arc<AtomicBool>,
     server_url: Arc<Mutex<String>>,
     handle: ksni::Handle<AppTray>,
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 impl Tray for TrayController {
     fn set_connected(&self, connected: bool) {
         self.connected.store(connected, Ordering::Relaxed);
@@ -21,55 +59,111 @@ impl Tray for TrayController {
             t.set_connected(connected);
         });
     }
+
+    fn set_server_url(&self, url: &str) {
+        *self.server_url.lock().unwrap() = url.to_string();
+        self.handle.update(|_| {});
+    }
+
+    fn set_profiles(&self, profiles: Vec<String>, active: &str) {
+        self.handle.update(|t| {
+            t.profiles = profiles;
+            t.active_profile = active.to_string();
+        });
+    }
+
+    fn set_send_targets(&self, profiles: Vec<String>) {
+        self.handle.update(|t| t.send_targets = profiles);
+    }
+
+    fn set_config_error(&self, error: Option<String>) {
+        self.handle.update(|t| t.config_error = error);
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.handle.update(|t| t.paused = paused);
+    }
+
+    fn set_direction(&self, direction: crate::config::Direction) {
+        self.handle.update(|t| t.direction = direction);
+    }
+
+    fn set_held(&self, reason: Option<String>) {
+        self.handle.update(|t| t.held = reason);
+    }
 }
 
-#[cfg(target_os = "linux")]
-pub fn start_tray(server_url: String, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> TrayController {
+#[cfg(all(target_os = "linux", feature = "tray"))]
+pub fn start_tray(server_url: String, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> Option<TrayController> {
+    // StatusNotifier items live on the session bus; SSH sessions and containers usually have none
+    let runtime_bus = std::env::var_os("XDG_RUNTIME_DIR").map(|d| std::path::Path::new(&d).join("bus"));
+    if std::env::var_os("DBUS_SESSION_BUS_ADDRESS").is_none() && !runtime_bus.is_some_and(|p| p.exists()) {
+        tracing::warn!("No D-Bus session bus found, running without tray icon");
+        return None;
+    }
+
     let connected = Arc::new(AtomicBool::new(false));
     let server_url_arc = Arc::new(Mutex::new(server_url.clone()));
     let tray = AppTray::new(server_url_arc.clone(), connected.clone(), cmd_tx.clone());
     let service = ksni::TrayService::new(tray);
     let handle = service.handle();
-    // Spawn the tray service on a separate thread
+    // Run the tray service on a separate thread; losing the bus only loses the icon
     std::thread::spawn(move || {
-        service.spawn();
+        if let Err(e) = service.run() {
+            tracing::warn!("System tray unavailable, continuing without it: {}", e);
+        }
     });
 
-    TrayController { connected, server_url: server_url_arc, handle }
+    Some(TrayController { connected, server_url: server_url_arc, handle })
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 struct AppTray {
     server_url: Arc<Mutex<String>>,
     connected: Arc<AtomicBool>,
+    config_error: Option<String>,
+    profiles: Vec<String>,
+    active_profile: String,
+    send_targets: Vec<String>,
+    paused: bool,
+    direction: crate::config::Direction,
+    held: Option<String>,
     cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>,
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 impl AppTray {
     fn new(server_url: Arc<Mutex<String>>, connected: Arc<AtomicBool>, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> Self {
-        Self { server_url, connected, cmd_tx }
+        Self { server_url, connected, config_error: None, profiles: Vec::new(), active_profile: String::new(), send_targets: Vec::new(), paused: false, direction: Default::default(), held: None, cmd_tx }
     }
     fn set_connected(&mut self, connected: bool) {
         self.connected.store(connected, Ordering::Relaxed);
     }
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", feature = "tray"))]
 impl ksni::Tray for AppTray {
     fn title(&self) -> String { "Shared Clipboard".into() }
 
+    fn watcher_offine(&self) -> bool {
+        // Keep the service alive so the icon appears once a tray host starts
+        tracing::info!("No system tray host available; syncing continues without a visible icon");
+        true
+    }
+
     fn icon_pixmap(&self) -> Vec<ksni::Icon> {
         // Generate a simple clipboard glyph with a small status dot.
-        fn make_icon(size: i32, connected: bool) -> ksni::Icon {
+        fn make_icon(size: i32, connected: bool, paused: bool) -> ksni::Icon {
             let s = size as usize;
             let mut data = vec![0u8; s * s * 4]; // RGBA
 
+            #[allow(clippy::too_many_arguments)]
             fn put(data: &mut [u8], s: usize, x: usize, y: usize, r: u8, g: u8, b: u8, a: u8) {
                 if x >= s || y >= s { return; }
                 let i = (y * s + x) * 4;
                 data[i] = r; data[i+1] = g; data[i+2] = b; data[i+3] = a;
             }
+            #[allow(clippy::too_many_arguments)]
             fn fill_rect(data: &mut [u8], s: usize, x0: usize, y0: usize, x1: usize, y1: usize, r: u8, g: u8, b: u8, a: u8) {
                 for y in y0..y1 { for x in x0..x1 { put(data, s, x, y, r, g, b, a); } }
             }
@@ -98,13 +192,13 @@ impl ksni::Tray for AppTray {
             let dot_r = (size as f32 * 0.12) as usize;
             let cx = right - dot_r - 2;
             let cy = bottom - dot_r - 2;
-            let (dr,dg,db) = if connected { (46u8, 204u8, 113u8) } else { (231u8, 76u8, 60u8) };
+            let (dr,dg,db) = if paused { (243u8, 156u8, 18u8) } else if connected { (46u8, 204u8, 113u8) } else { (231u8, 76u8, 60u8) };
             for y in 0..(dot_r*2) {
                 for x in 0..(dot_r*2) {
                     let dx = x as i32 - dot_r as i32;
                     let dy = y as i32 - dot_r as i32;
                     if dx*dx + dy*dy <= (dot_r as i32)*(dot_r as i32) {
-                        put(&mut data, s, (cx + x) as usize, (cy + y) as usize, dr, dg, db, 255);
+                        put(&mut data, s, cx + x, cy + y, dr, dg, db, 255);
                     }
                 }
             }
@@ -113,33 +207,116 @@ impl ksni::Tray for AppTray {
         }
 
         let connected = self.connected.load(Ordering::Relaxed);
-        vec![make_icon(16, connected), make_icon(24, connected), make_icon(32, connected)]
+        let paused = self.paused;
+        vec![make_icon(16, connected, paused), make_icon(24, connected, paused), make_icon(32, connected, paused)]
     }
 
     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
         let current_url = self.server_url.lock().unwrap().clone();
-        let status_text = if self.connected.load(Ordering::Relaxed) {
+        let status_text = if self.paused {
+            format!("Paused • {}", current_url)
+        } else if self.connected.load(Ordering::Relaxed) {
             format!("Connected • {}", current_url)
         } else {
             format!("Disconnected • {}", current_url)
         };
 
-        vec![
+        let mut items = vec![
             ksni::MenuItem::Standard(ksni::menu::StandardItem {
                 label: status_text,
                 enabled: false,
                 ..Default::default()
             }),
+        ];
+        if let Some(error) = &self.config_error {
+            items.push(ksni::MenuItem::Standard(ksni::menu::StandardItem {
+                label: format!("Config error: {}", error),
+                enabled: false,
+                ..Default::default()
+            }));
+        }
+        if let Some(reason) = &self.held {
+            items.push(ksni::MenuItem::Standard(ksni::menu::StandardItem {
+                label: format!("Send held clipboard ({})", reason),
+                activate: Box::new(|me: &mut Self| { let _ = me.cmd_tx.send(crate::Command::SendHeld); }),
+                ..Default::default()
+            }));
+        }
+        items.push(ksni::MenuItem::Separator);
+        if self.paused {
+            items.push(ksni::MenuItem::Standard(ksni::menu::StandardItem {
+                label: "Resume sync".into(),
+                activate: Box::new(|me: &mut Self| { let _ = me.cmd_tx.send(crate::Command::Resume); }),
+                ..Default::default()
+            }));
+        } else {
+            items.push(ksni::MenuItem::SubMenu(ksni::menu::SubMenu {
+                label: "Pause sync".into(),
+                submenu: PAUSE_CHOICES.iter().map(|&(label, resume_after)| {
+                    ksni::MenuItem::Standard(ksni::menu::StandardItem {
+                        label: label.into(),
+                        activate: Box::new(move |me: &mut Self| { let _ = me.cmd_tx.send(crate::Command::Pause(resume_after)); }),
+                        ..Default::default()
+                    })
+                }).collect(),
+                ..Default::default()
+            }));
+        }
+        items.push(ksni::MenuItem::SubMenu(ksni::menu::SubMenu {
+            label: "Direction".into(),
+            submenu: vec![ksni::MenuItem::RadioGroup(ksni::menu::RadioGroup {
+                selected: crate::config::Direction::ALL.iter().position(|d| *d == self.direction).unwrap_or(0),
+                select: Box::new(|me: &mut Self, index| {
+                    if let Some(direction) = crate::config::Direction::ALL.get(index) {
+                        let _ = me.cmd_tx.send(crate::Command::SetDirection(*direction));
+                    }
+                }),
+                options: crate::config::Direction::ALL.iter().map(|d| ksni::menu::RadioItem { label: d.label().into(), ..Default::default() }).collect(),
+            })],
+            ..Default::default()
+        }));
+        // Quick switching only makes sense with more than one profile
+        if self.profiles.len() > 1 {
+            items.push(ksni::MenuItem::Separator);
+            items.push(ksni::MenuItem::SubMenu(ksni::menu::SubMenu {
+                label: "Profile".into(),
+                submenu: vec![ksni::MenuItem::RadioGroup(ksni::menu::RadioGroup {
+                    selected: self.profiles.iter().position(|p| *p == self.active_profile).unwrap_or(0),
+                    select: Box::new(|me: &mut Self, index| {
+                        if let Some(name) = me.profiles.get(index) {
+                            let _ = me.cmd_tx.send(crate::Command::SwitchProfile(name.clone()));
+                        }
+                    }),
+                    options: self.profiles.iter().map(|p| ksni::menu::RadioItem { label: p.clone(), ..Default::default() }).collect(),
+                })],
+                ..Default::default()
+            }));
+        }
+        if !self.send_targets.is_empty() {
+            items.push(ksni::MenuItem::SubMenu(ksni::menu::SubMenu {
+                label: "Send clipboard to".into(),
+                submenu: self.send_targets.iter().map(|name| {
+                    let name = name.clone();
+                    ksni::MenuItem::Standard(ksni::menu::StandardItem {
+                        label: name.clone(),
+                        activate: Box::new(move |me: &mut Self| { let _ = me.cmd_tx.send(crate::Command::SendTo(name.clone())); }),
+                        ..Default::default()
+                    })
+                }).collect(),
+                ..Default::default()
+            }));
+        }
+        items.extend([
+            #[cfg(feature = "gui")]
             ksni::MenuItem::Separator,
+            #[cfg(feature = "gui")]
             ksni::MenuItem::Standard(ksni::menu::StandardItem {
                 label: "Settings".into(),
-                activate: Box::new(|me| {
-                    let current_url = me.server_url.lock().unwrap().clone();
+                activate: Box::new(|me: &mut Self| {
                     let connected = me.connected.load(Ordering::Relaxed);
-                    if let Some(new_url) = crate::settings::open_settings_blocking(current_url.clone(), connected) {
-                        *(me.server_url.lock().unwrap()) = new_url.clone();
-                        let _ = me.cmd_tx.send(crate::Command::SetUrl(new_url));
-                        me.set_connected(me.connected.load(Ordering::Relaxed));
+                    // The window saves the config file; the reload updates URL and profiles
+                    if crate::settings::open_settings_blocking(connected) {
+                        let _ = me.cmd_tx.send(crate::Command::ReloadConfig);
                     }
                 }),
                 ..Default::default()
@@ -147,18 +324,11 @@ impl ksni::Tray for AppTray {
             ksni::MenuItem::Separator,
             ksni::MenuItem::Standard(ksni::menu::StandardItem {
                 label: "Quit".into(),
-                activate: Box::new(|me| { let _ = me.cmd_tx.send(crate::Command::Quit); }),
+                activate: Box::new(|me: &mut Self| { let _ = me.cmd_tx.send(crate::Command::Quit); }),
                 ..Default::default()
             }),
-        ]
+        ]);
+        items
     }
 }
 
-// Stubs for non-Linux targets so the code compiles conditionally
-#[cfg(not(target_os = "linux"))]
-pub struct TrayController;
-#[cfg(not(target_os = "linux"))]
-impl Tray for TrayController { fn set_connected(&self, _connected: bool) {} }
-#[cfg(not(target_os = "linux"))]
-pub fn start_tray(_server_url: String) -> TrayController { TrayController }
-
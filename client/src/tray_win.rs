@@ -1,7 +1,7 @@
 // Windows system tray using tray-icon crate
-// Provides Settings (opens settings window), and Quit.
+// Provides pause/resume, a direction switcher, a profile switcher, "Send clipboard to", Settings (opens settings window), and Quit.
 
-#![cfg(target_os = "windows")]
+#![cfg(all(target_os = "windows", feature = "tray"))]
 
 use std::sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex};
 use tray_icon::{TrayIconBuilder, menu::{MenuBuilder, MenuItem, SubmenuBuilder, MenuId, PredefinedMenuItem}, TrayIcon};
@@ -11,76 +11,203 @@ use crate::tray::Tray;
 pub struct TrayController {
     connected: Arc<AtomicBool>,
     server_url: Arc<Mutex<String>>,
+    menu_state: Mutex<MenuState>,
 tray: Arc<Mutex<Option<TrayIcon>>>,
 }
 
+// Dynamic parts of the menu, rebuilt whenever the profiles change
+#[derive(Default)]
+struct MenuState {
+    profiles: Vec<String>,
+    active: String,
+    send_targets: Vec<String>,
+    paused: bool,
+    direction: crate::config::Direction,
+    held: Option<String>,
+}
+
+impl TrayController {
+    fn rebuild_menu(&self) {
+        let menu = build_menu(&self.server_url.lock().unwrap(), &self.menu_state.lock().unwrap());
+        if let Some(tray) = self.tray.lock().unwrap().as_ref() {
+            tray.set_menu(Some(Box::new(menu.build())));
+        }
+    }
+}
+
 impl Tray for TrayController {
     fn set_connected(&self, connected: bool) {
         self.connected.store(connected, Ordering::Relaxed);
     }
+
+    fn set_server_url(&self, url: &str) {
+        *self.server_url.lock().unwrap() = url.to_string();
+        if let Some(tray) = self.tray.lock().unwrap().as_ref() {
+            if let Some(menu) = tray.menu() {
+                let _ = menu.update_item(&MenuId::new("status"), &format!("Connected • {}", url));
+            }
+        }
+    }
+
+    fn set_profiles(&self, profiles: Vec<String>, active: &str) {
+        {
+            let mut state = self.menu_state.lock().unwrap();
+            state.profiles = profiles;
+            state.active = active.to_string();
+        }
+        self.rebuild_menu();
+    }
+
+    fn set_send_targets(&self, profiles: Vec<String>) {
+        self.menu_state.lock().unwrap().send_targets = profiles;
+        self.rebuild_menu();
+    }
+
+    fn set_config_error(&self, error: Option<String>) {
+        let tooltip = match error {
+            Some(e) => format!("Shared Clipboard\nConfig error: {}", e),
+            None => "Shared Clipboard".to_string(),
+        };
+        if let Some(tray) = self.tray.lock().unwrap().as_ref() {
+            let _ = tray.set_tooltip(Some(tooltip));
+        }
+    }
+
+    fn set_paused(&self, paused: bool) {
+        self.menu_state.lock().unwrap().paused = paused;
+        if let Some(tray) = self.tray.lock().unwrap().as_ref() {
+            let _ = tray.set_icon(Some(generated_icon(true, paused)));
+        }
+        self.rebuild_menu();
+    }
+
+    fn set_direction(&self, direction: crate::config::Direction) {
+        self.menu_state.lock().unwrap().direction = direction;
+        self.rebuild_menu();
+    }
+
+    fn set_held(&self, reason: Option<String>) {
+        self.menu_state.lock().unwrap().held = reason;
+        self.rebuild_menu();
+    }
 }
 
-pub fn start_tray(server_url: String, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> TrayController {
+pub fn start_tray(server_url: String, cmd_tx: tokio::sync::mpsc::UnboundedSender<crate::Command>) -> Option<TrayController> {
     let connected = Arc::new(AtomicBool::new(false));
     let server_url_arc = Arc::new(Mutex::new(server_url.clone()));
 
-    let mut menu = MenuBuilder::new();
-    // Disabled status item
-    let status_id = MenuId::new("status");
-    menu = menu.item("Connected • ")
-               .with_id(status_id.clone())
-               .enabled(false)
-               .separator()
-               .item("Settings")
-               .separator()
-               .item("Quit");
+    let menu = build_menu(&server_url, &MenuState::default());
 
-    let icon = generated_icon(true);
+    let icon = generated_icon(true, false);
 
-    let tray = TrayIconBuilder::new()
+    let tray = match TrayIconBuilder::new()
         .with_menu(Box::new(menu.build()))
         .with_tooltip("Shared Clipboard")
         .with_icon(icon)
         .build()
-        .expect("Failed to create tray icon");
+    {
+        Ok(tray) => tray,
+        Err(e) => {
+            tracing::warn!("System tray unavailable, continuing without it: {}", e);
+            return None;
+        }
+    };
 
     let tray_arc = Arc::new(Mutex::new(Some(tray)));
 
     // Menu callbacks
     {
         use tray_icon::menu::MenuEvent;
-        let server_url_for_cb = server_url_arc.clone();
         let connected_for_cb = connected.clone();
-        let tray_ref = tray_arc.clone();
         let cmd_tx = cmd_tx.clone();
         std::thread::spawn(move || {
             for event in MenuEvent::receiver().iter() {
                 match event.id.as_ref() {
+                    #[cfg(feature = "gui")]
                     "Settings" => {
-                        let url = server_url_for_cb.lock().unwrap().clone();
                         let is_conn = connected_for_cb.load(Ordering::Relaxed);
-                        if let Some(new_url) = crate::settings::open_settings_blocking(url, is_conn) {
-                            *server_url_for_cb.lock().unwrap() = new_url.clone();
-                            let _ = cmd_tx.send(crate::Command::SetUrl(new_url));
-                            // Update status text
-                            if let Some(tray) = tray_ref.lock().unwrap().as_ref() {
-                                if let Some(menu) = tray.menu() {
-                                    let _ = menu.update_item(&status_id, &format!("Connected • {}", *server_url_for_cb.lock().unwrap()));
-                                }
-                            }
+                        // The window saves the config file; the reload updates URL and profiles
+                        if crate::settings::open_settings_blocking(is_conn) {
+                            let _ = cmd_tx.send(crate::Command::ReloadConfig);
                         }
                     }
                     "Quit" => { let _ = cmd_tx.send(crate::Command::Quit); }
+                    "resume" => { let _ = cmd_tx.send(crate::Command::Resume); }
+                    "send-held" => { let _ = cmd_tx.send(crate::Command::SendHeld); }
+                    id if id.starts_with("pause:") => {
+                        if let Some(&(_, resume_after)) = id["pause:".len()..].parse::<usize>().ok().and_then(|i| crate::tray::PAUSE_CHOICES.get(i)) {
+                            let _ = cmd_tx.send(crate::Command::Pause(resume_after));
+                        }
+                    }
+                    id if id.starts_with("direction:") => {
+                        if let Some(&direction) = id["direction:".len()..].parse::<usize>().ok().and_then(|i| crate::config::Direction::ALL.get(i)) {
+                            let _ = cmd_tx.send(crate::Command::SetDirection(direction));
+                        }
+                    }
+                    id if id.starts_with("profile:") => {
+                        let _ = cmd_tx.send(crate::Command::SwitchProfile(id["profile:".len()..].to_string()));
+                    }
+                    id if id.starts_with("send:") => {
+                        let _ = cmd_tx.send(crate::Command::SendTo(id["send:".len()..].to_string()));
+                    }
                     _ => {}
                 }
             }
         });
     }
 
-    TrayController { connected, server_url: server_url_arc, tray: tray_arc }
+    Some(TrayController { connected, server_url: server_url_arc, menu_state: Mutex::new(MenuState::default()), tray: tray_arc })
+}
+
+fn build_menu(server_url: &str, state: &MenuState) -> MenuBuilder {
+    let mut menu = MenuBuilder::new();
+    // Disabled status item
+    menu = menu.item(&format!("Connected • {}", server_url))
+               .with_id(MenuId::new("status"))
+               .enabled(false)
+               .separator();
+    if let Some(reason) = &state.held {
+        menu = menu.item(&format!("Send held clipboard ({})", reason)).with_id(MenuId::new("send-held")).separator();
+    }
+    if state.paused {
+        menu = menu.item("Resume sync").with_id(MenuId::new("resume")).separator();
+    } else {
+        let mut submenu = SubmenuBuilder::new().text("Pause sync");
+        for (i, (label, _)) in crate::tray::PAUSE_CHOICES.iter().enumerate() {
+            submenu = submenu.item(label).with_id(MenuId::new(format!("pause:{}", i)));
+        }
+        menu = menu.submenu(submenu.build()).separator();
+    }
+    let mut submenu = SubmenuBuilder::new().text("Direction");
+    for (i, direction) in crate::config::Direction::ALL.iter().enumerate() {
+        let label = if *direction == state.direction { format!("● {}", direction.label()) } else { format!("   {}", direction.label()) };
+        submenu = submenu.item(&label).with_id(MenuId::new(format!("direction:{}", i)));
+    }
+    menu = menu.submenu(submenu.build()).separator();
+    if state.profiles.len() > 1 {
+        let mut submenu = SubmenuBuilder::new().text("Profile");
+        for name in &state.profiles {
+            let label = if *name == state.active { format!("● {}", name) } else { format!("   {}", name) };
+            submenu = submenu.item(&label).with_id(MenuId::new(format!("profile:{}", name)));
+        }
+        menu = menu.submenu(submenu.build()).separator();
+    }
+    if !state.send_targets.is_empty() {
+        let mut submenu = SubmenuBuilder::new().text("Send clipboard to");
+        for name in &state.send_targets {
+            submenu = submenu.item(name).with_id(MenuId::new(format!("send:{}", name)));
+        }
+        menu = menu.submenu(submenu.build()).separator();
+    }
+    #[cfg(feature = "gui")]
+    {
+        menu = menu.item("Settings")
+                   .separator();
+    }
+    menu.item("Quit")
 }
 
-fn generated_icon(connected: bool) -> tray_icon::icon::Icon {
+fn generated_icon(connected: bool, paused: bool) -> tray_icon::icon::Icon {
     let size = 32;
     let s = size as usize;
     let mut rgba = vec![0u8; s*s*4];
@@ -94,7 +221,7 @@ fn generated_icon(connected: bool) -> tray_icon::icon::Icon {
     for y in pad..(s-pad) { let i=((y*s)+pad)*4; rgba[i..i+4].copy_from_slice(&[60,60,70,255]); let j=((y*s)+(s-pad-1))*4; rgba[j..j+4].copy_from_slice(&[60,60,70,255]); }
     // Status dot
     let r = 4i32; let cx = s as i32 - r - 4; let cy = s as i32 - r - 4;
-    let (dr,dg,db) = if connected { (46,204,113) } else { (231,76,60) };
+    let (dr,dg,db) = if paused { (243,156,18) } else if connected { (46,204,113) } else { (231,76,60) };
     for dy in -r..=r { for dx in -r..=r { if dx*dx+dy*dy<=r*r { let X=(cx+dx) as usize; let Y=(cy+dy) as usize; let i=(Y*s+X)*4; rgba[i..i+4].copy_from_slice(&[dr as u8,dg as u8,db as u8,255]); } } }
     tray_icon::icon::Icon::from_rgba(rgba, size, size).expect("icon")
 }
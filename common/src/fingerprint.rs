@@ -0,0 +1,116 @@
+// Content fingerprints: SHA-256 over a normalised representation of a clipboard update.
+// The server and every client compute them the same way, so they can be compared across
+// machines, platforms and builds (unlike std's DefaultHasher). Used for change detection,
+// echo suppression and as history IDs.
+//
+// Normalisation makes the platforms agree on what "the same content" is:
+// - text: CRLF line endings become LF, trailing whitespace is ignored
+// - html: surrounding whitespace is ignored, and so is HTML that only repeats the text
+//   (Linux apps that offer nothing but text/html)
+// - rtf: trailing whitespace and NULs are ignored
+// - empty representations count as missing; timestamps and other metadata are not covered
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Bumped whenever the normalisation or encoding changes
+const VERSION: &[u8] = b"clipboard-fingerprint-v1";
+
+// The representations of one update that a fingerprint covers.
+pub struct Content<'a> {
+    pub text: &'a str,
+    pub html: Option<&'a str>,
+    pub rtf: Option<&'a str>,
+    // Base64, as carried in updates
+    pub image: Option<&'a str>,
+    // MIME type → base64 payload
+    pub extra: &'a BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Fingerprint([u8; 32]);
+
+impl Fingerprint {
+    pub fn of(content: &Content) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(VERSION);
+
+        let text = content.text.replace("\r\n", "\n");
+        let text = text.trim_end();
+        field(&mut hasher, "text", text);
+        if let Some(html) = content.html.map(str::trim).filter(|html| !html.is_empty() && *html != text.trim()) {
+            field(&mut hasher, "html", html);
+        }
+        if let Some(rtf) = content.rtf.map(|rtf| rtf.trim_end_matches(|c: char| c == '\0' || c.is_whitespace())).filter(|rtf| !rtf.is_empty()) {
+            field(&mut hasher, "rtf", rtf);
+        }
+        if let Some(image) = content.image.filter(|image| !image.is_empty()) {
+            field(&mut hasher, "image", image);
+        }
+        for (mime_type, payload) in content.extra {
+            field(&mut hasher, &format!("mime:{}", mime_type.to_ascii_lowercase()), payload);
+        }
+        Self(hasher.finalize().into())
+    }
+}
+
+// Tag and length prefix every field, so no two different updates encode the same way
+fn field(hasher: &mut Sha256, tag: &str, value: &str) {
+    hasher.update(tag.as_bytes());
+    hasher.update([0]);
+    hasher.update((value.len() as u64).to_be_bytes());
+    hasher.update(value.as_bytes());
+}
+
+// Lowercase hex, 64 characters
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str) -> Fingerprint {
+        Fingerprint::of(&Content { text, html: None, rtf: None, image: None, extra: &BTreeMap::new() })
+    }
+
+    #[test]
+    fn is_stable() {
+        // Changing this value breaks echo suppression between old and new builds; bump VERSION instead
+        assert_eq!(text("hello").to_string(), "71c59d202ad89f911f3079e2401bdc957c67a71d7183740f054a7737179142f4");
+    }
+
+    #[test]
+    fn normalises_line_endings_and_trailing_whitespace() {
+        assert_eq!(text("a\r\nb\r\n"), text("a\nb"));
+        assert_ne!(text("a b"), text("a  b"));
+    }
+
+    #[test]
+    fn ignores_html_that_repeats_the_text() {
+        let extra = BTreeMap::new();
+        let html = |text, html| Fingerprint::of(&Content { text, html: Some(html), rtf: None, image: None, extra: &extra });
+        assert_eq!(html("<b>x</b>", " <b>x</b>\n"), text("<b>x</b>"));
+        assert_ne!(html("x", "<b>x</b>"), text("x"));
+    }
+
+    #[test]
+    fn fields_do_not_run_together() {
+        let extra = BTreeMap::new();
+        let with = |text, rtf| Fingerprint::of(&Content { text, html: None, rtf, image: None, extra: &extra });
+        assert_ne!(with("ab", None), with("a", Some("b")));
+        assert_eq!(with("a", Some("")), with("a", None));
+        assert_eq!(with("a", Some("{\\rtf1 a}\0")), with("a", Some("{\\rtf1 a}")));
+    }
+
+    #[test]
+    fn covers_extra_formats() {
+        let svg = BTreeMap::from([("image/svg+xml".to_string(), "PHN2Zy8+".to_string())]);
+        let with_svg = Fingerprint::of(&Content { text: "", html: None, rtf: None, image: None, extra: &svg });
+        assert_ne!(with_svg, text(""));
+    }
+}
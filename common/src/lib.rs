@@ -0,0 +1,5 @@
+// Code shared by the server and the client.
+
+pub mod fingerprint;
+
+pub use fingerprint::{Content, Fingerprint};
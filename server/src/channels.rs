@@ -0,0 +1,134 @@
+// Independent clipboards on one server, e.g. one per team or per person.
+// Every clipboard request picks its channel with the `x-clipboard-channel` header or a
+// `channel` query parameter (browsers cannot set WebSocket headers); requests with neither
+// use `default`. Each channel has its own current entry, history, sequence numbers and
+// broadcast, so updates, clears and expiries never cross channels. Channels are created on
+// first use, up to CLIPBOARD_MAX_CHANNELS.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{broadcast, Mutex, Notify};
+use warp::Filter;
+
+use crate::history::{History, SharedHistory};
+use crate::persist::SavedChannel;
+use crate::{ClipboardData, ClipboardEvent, ClipboardState};
+
+pub const DEFAULT_CHANNEL: &str = "default";
+
+const MAX_NAME_LEN: usize = 64;
+
+pub struct Channel {
+    pub name: String,
+    pub state: ClipboardState,
+    pub history: SharedHistory,
+    pub broadcast_tx: broadcast::Sender<ClipboardEvent>,
+    changed: Arc<Notify>,
+}
+
+impl Channel {
+    // Note a change to the current entry or history, for the state file
+    pub fn mark_changed(&self) {
+        self.changed.notify_one();
+    }
+}
+
+pub type SharedChannel = Arc<Channel>;
+
+pub struct Channels {
+    channels: Mutex<HashMap<String, SharedChannel>>,
+    history_size: usize,
+    broadcast_capacity: usize,
+    max_channels: usize,
+    // Shared by every channel
+    changed: Arc<Notify>,
+}
+
+pub type SharedChannels = Arc<Channels>;
+
+impl Channels {
+    pub fn new(history_size: usize, broadcast_capacity: usize, max_channels: usize) -> Self {
+        Self { channels: Mutex::new(HashMap::new()), history_size, broadcast_capacity, max_channels, changed: Arc::new(Notify::new()) }
+    }
+
+    // Channel `name`, created empty on first use; None once the channel limit is reached.
+    pub async fn get(&self, name: &str) -> Option<SharedChannel> {
+        let mut channels = self.channels.lock().await;
+        if let Some(channel) = channels.get(name) {
+            return Some(channel.clone());
+        }
+        if channels.len() >= self.max_channels {
+            return None;
+        }
+        let channel = self.create(name, History::new(self.history_size), None);
+        channels.insert(name.to_string(), channel.clone());
+        Some(channel)
+    }
+
+    // Recreate channel `name` from the state file. Used at startup, before any request.
+    pub async fn restore(&self, name: &str, saved: SavedChannel) -> SharedChannel {
+        let history = History::restore(self.history_size, saved.history, saved.current.as_ref());
+        let channel = self.create(name, history, saved.current);
+        self.channels.lock().await.insert(name.to_string(), channel.clone());
+        channel
+    }
+
+    pub async fn all(&self) -> Vec<SharedChannel> {
+        let mut channels: Vec<SharedChannel> = self.channels.lock().await.values().cloned().collect();
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+        channels
+    }
+
+    // Resolves once any channel changed since the last call.
+    pub async fn changed(&self) {
+        self.changed.notified().await;
+    }
+
+    fn create(&self, name: &str, history: History, state: Option<ClipboardData>) -> SharedChannel {
+        let (broadcast_tx, _) = broadcast::channel(self.broadcast_capacity);
+        Arc::new(Channel {
+            name: name.to_string(),
+            history: Arc::new(Mutex::new(history)),
+            state: Arc::new(Mutex::new(state)),
+            broadcast_tx,
+            changed: self.changed.clone(),
+        })
+    }
+}
+
+// Letters, digits, `-`, `_` and `.`, so names are safe in logs, URLs and the state file.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[derive(Debug)]
+pub struct InvalidChannel;
+
+impl warp::reject::Reject for InvalidChannel {}
+
+#[derive(Debug)]
+pub struct TooManyChannels;
+
+impl warp::reject::Reject for TooManyChannels {}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChannelQuery {
+    channel: Option<String>,
+}
+
+// Resolves the channel a request addresses.
+pub fn filter(channels: SharedChannels) -> impl Filter<Extract = (SharedChannel,), Error = warp::Rejection> + Clone {
+    warp::header::optional::<String>("x-clipboard-channel")
+        .and(warp::query::<ChannelQuery>())
+        .and(warp::any().map(move || channels.clone()))
+        .and_then(|header: Option<String>, query: ChannelQuery, channels: SharedChannels| async move {
+            let name = header.or(query.channel).unwrap_or_else(|| DEFAULT_CHANNEL.to_string());
+            if !is_valid_name(&name) {
+                return Err(warp::reject::custom(InvalidChannel));
+            }
+            channels.get(&name).await.ok_or_else(|| warp::reject::custom(TooManyChannels))
+        })
+}
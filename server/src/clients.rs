@@ -0,0 +1,72 @@
+// Connected WebSocket clients and their fan-out statistics.
+// Each client gets its own broadcast receiver; the stats here are updated by the
+// client's outgoing task and read by the stats endpoint.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+pub type Clients = Arc<Mutex<HashMap<String, ClientHandle>>>;
+
+pub struct ClientHandle {
+    // Direct (non-broadcast) messages to this client
+    pub tx: tokio::sync::mpsc::UnboundedSender<warp::ws::Message>,
+    pub channel: String,
+    pub stats: Arc<ClientStats>,
+}
+
+#[derive(Default)]
+pub struct ClientStats {
+    queue_depth: AtomicUsize,
+    max_queue_depth: AtomicUsize,
+    lag_events: AtomicU64,
+    skipped_updates: AtomicU64,
+}
+
+impl ClientStats {
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.store(depth, Ordering::Relaxed);
+        self.max_queue_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub fn record_lag(&self, skipped: u64) {
+        self.lag_events.fetch_add(1, Ordering::Relaxed);
+        self.skipped_updates.fetch_add(skipped, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, id: &str, channel: &str) -> ClientStatsSnapshot {
+        ClientStatsSnapshot {
+            id: id.to_string(),
+            channel: channel.to_string(),
+            queue_depth: self.queue_depth.load(Ordering::Relaxed),
+            max_queue_depth: self.max_queue_depth.load(Ordering::Relaxed),
+            lag_events: self.lag_events.load(Ordering::Relaxed),
+            skipped_updates: self.skipped_updates.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientStatsSnapshot {
+    pub id: String,
+    pub channel: String,
+    // Broadcast updates waiting to be written to this client's socket
+    pub queue_depth: usize,
+    pub max_queue_depth: usize,
+    // Times the client fell behind the broadcast channel and was resynchronised
+    pub lag_events: u64,
+    // Intermediate updates dropped while coalescing after a lag
+    pub skipped_updates: u64,
+}
+
+pub async fn client_stats(clients: Clients) -> Result<impl warp::Reply, warp::Rejection> {
+    let clients = clients.lock().await;
+    let mut stats: Vec<ClientStatsSnapshot> = clients
+        .iter()
+        .map(|(id, handle)| handle.stats.snapshot(id, &handle.channel))
+        .collect();
+    stats.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(warp::reply::json(&stats))
+}
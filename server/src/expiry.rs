@@ -0,0 +1,53 @@
+// Time-limited clipboard entries.
+// An entry's own `ttl`, or CLIPBOARD_TTL when it has none, sets when it expires. On
+// expiry it is cleared like an explicit DELETE (see `clear` in main.rs).
+
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::channels::{Channel, SharedChannel};
+use crate::ClipboardData;
+
+// Server-wide TTL in seconds for entries that do not set one; 0 or unset means never.
+fn default_ttl() -> Option<u64> {
+    static DEFAULT_TTL: OnceLock<Option<u64>> = OnceLock::new();
+    *DEFAULT_TTL.get_or_init(|| crate::env_u64("CLIPBOARD_TTL").filter(|&ttl| ttl > 0))
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+// Set `expires_at` from the entry's TTL or the server default.
+pub fn stamp(data: &mut ClipboardData) {
+    data.ttl = data.ttl.filter(|&ttl| ttl > 0).or_else(default_ttl);
+    data.expires_at = data.ttl.map(|ttl| now() + ttl);
+}
+
+pub fn is_expired(data: &ClipboardData) -> bool {
+    data.expires_at.is_some_and(|at| at <= now())
+}
+
+// Expire `data` in `channel` when its time comes. Entries without an expiry are left alone.
+pub fn schedule(data: &ClipboardData, channel: &SharedChannel) {
+    let (Some(seq), Some(expires_at)) = (data.seq, data.expires_at) else { return };
+    let delay = Duration::from_secs(expires_at.saturating_sub(now()));
+    let channel = channel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        if still_due(&channel, seq, expires_at).await {
+            crate::clear(seq, "expired", &channel).await;
+        }
+    });
+}
+
+// Whether entry `seq` still expires at `expires_at`. Sending its content again may have moved the
+// expiry, in which case a newer timer is responsible for it.
+async fn still_due(channel: &Channel, seq: u64, expires_at: u64) -> bool {
+    let current = channel.state.lock().await.as_ref().filter(|data| data.seq == Some(seq)).map(|data| data.expires_at);
+    let scheduled = match current {
+        Some(current) => Some(current),
+        None => channel.history.lock().await.expires_at(seq),
+    };
+    scheduled.is_none_or(|at| at == Some(expires_at))
+}
@@ -0,0 +1,103 @@
+// Liveness and readiness endpoints for container orchestration.
+// Neither endpoint touches clipboard contents, so they are safe to expose to health checkers.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use warp::http::StatusCode;
+
+use crate::channels::SharedChannels;
+
+const STORAGE_CHECK_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub struct Health {
+    started: Instant,
+    listening: AtomicBool,
+}
+
+impl Health {
+    pub fn new() -> Self {
+        Self { started: Instant::now(), listening: AtomicBool::new(false) }
+    }
+
+    pub fn set_listening(&self, listening: bool) {
+        self.listening.store(listening, Ordering::Relaxed);
+    }
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    version: &'static str,
+    uptime_seconds: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    checks: Option<ReadinessChecks>,
+}
+
+#[derive(Serialize)]
+struct ReadinessChecks {
+    listener: bool,
+    storage: bool,
+}
+
+impl HealthResponse {
+    fn new(health: &Health, status: &'static str, checks: Option<ReadinessChecks>) -> Self {
+        Self {
+            status,
+            version: env!("CARGO_PKG_VERSION"),
+            uptime_seconds: health.started.elapsed().as_secs(),
+            checks,
+        }
+    }
+}
+
+pub async fn healthz(health: Arc<Health>) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&HealthResponse::new(&health, "ok", None)))
+}
+
+pub async fn readyz(
+    health: Arc<Health>,
+    channels: SharedChannels,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let listener = health.listening.load(Ordering::Relaxed);
+    // A channel or state lock that cannot be taken in time means requests would hang. Only
+    // existing channels are checked: creating one here would use up a CLIPBOARD_MAX_CHANNELS slot
+    let storage = tokio::time::timeout(STORAGE_CHECK_TIMEOUT, async {
+        for channel in channels.all().await {
+            drop(channel.state.lock().await);
+        }
+    })
+    .await
+    .is_ok();
+
+    let ready = listener && storage;
+    let (status, code) = if ready {
+        ("ok", StatusCode::OK)
+    } else {
+        ("unavailable", StatusCode::SERVICE_UNAVAILABLE)
+    };
+    let body = HealthResponse::new(&health, status, Some(ReadinessChecks { listener, storage }));
+    Ok(warp::reply::with_status(warp::reply::json(&body), code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::Channels;
+    use warp::Reply;
+
+    #[tokio::test]
+    async fn readiness_check_creates_no_channel() {
+        let health = Arc::new(Health::new());
+        health.set_listening(true);
+        let channels = Arc::new(Channels::new(10, 16, 1));
+        let response = readyz(health.clone(), channels.clone()).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(channels.all().await.is_empty());
+        // The only slot is still free for a real channel
+        assert!(channels.get("team").await.is_some());
+        let response = readyz(health, channels).await.unwrap().into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
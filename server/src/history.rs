@@ -0,0 +1,104 @@
+// Bounded in-memory history of clipboard updates.
+// Every published update gets a monotonically increasing sequence number, which
+// SSE consumers use as their event id to resume after a reconnect. Entries are also
+// identified by their content fingerprint: the same content is only listed once.
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+use crate::channels::SharedChannel;
+use crate::ClipboardData;
+
+pub type SharedHistory = Arc<Mutex<History>>;
+
+pub struct History {
+    entries: VecDeque<ClipboardData>,
+    capacity: usize,
+    next_seq: u64,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            next_seq: 1,
+        }
+    }
+
+    // History loaded from the state file (oldest first) plus the saved current entry, so sequence
+    // numbers continue where they stopped.
+    pub fn restore(capacity: usize, saved: Vec<ClipboardData>, current: Option<&ClipboardData>) -> Self {
+        let mut history = Self::new(capacity);
+        for data in saved.into_iter().chain(current.cloned()) {
+            // The state file only holds published entries; the current one is normally in the saved history already
+            let Some(seq) = data.seq.filter(|seq| history.get(*seq).is_none()) else { continue };
+            history.next_seq = history.next_seq.max(seq + 1);
+            if history.entries.len() == history.capacity {
+                history.entries.pop_front();
+            }
+            history.entries.push_back(data);
+        }
+        history
+    }
+
+    // Assign the next sequence number to `data` and record it, replacing an older entry with the same content.
+    pub fn push(&mut self, mut data: ClipboardData) -> ClipboardData {
+        data.seq = Some(self.next_seq);
+        self.next_seq += 1;
+        if data.fingerprint.is_some() {
+            self.entries.retain(|entry| entry.fingerprint != data.fingerprint);
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(data.clone());
+        data
+    }
+
+    pub fn get(&self, seq: u64) -> Option<ClipboardData> {
+        self.entries.iter().find(|entry| entry.seq == Some(seq)).cloned()
+    }
+
+    // Drop the entry with sequence number `seq` (cleared or expired).
+    pub fn remove(&mut self, seq: u64) {
+        self.entries.retain(|entry| entry.seq != Some(seq));
+    }
+
+    // Update the expiry of entry `seq` after its content was sent again.
+    pub fn set_expiry(&mut self, seq: u64, ttl: Option<u64>, expires_at: Option<u64>) {
+        if let Some(entry) = self.entries.iter_mut().find(|entry| entry.seq == Some(seq)) {
+            entry.ttl = ttl;
+            entry.expires_at = expires_at;
+        }
+    }
+
+    // When entry `seq` expires: None when it is gone, Some(None) when it never expires.
+    pub fn expires_at(&self, seq: u64) -> Option<Option<u64>> {
+        self.entries.iter().find(|entry| entry.seq == Some(seq)).map(|entry| entry.expires_at)
+    }
+
+    // All retained entries, newest first.
+    pub fn entries(&self) -> Vec<ClipboardData> {
+        self.entries.iter().rev().cloned().collect()
+    }
+
+    // Sequence number of the latest published entry, 0 before the first.
+    pub fn last_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    // Entries published after `seq`, oldest first.
+    pub fn since(&self, seq: u64) -> Vec<ClipboardData> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.seq.is_some_and(|s| s > seq))
+            .cloned()
+            .collect()
+    }
+}
+
+pub async fn get_history(channel: SharedChannel) -> Result<impl warp::Reply, warp::Rejection> {
+    Ok(warp::reply::json(&channel.history.lock().await.entries()))
+}
@@ -1,10 +1,28 @@
 use futures_util::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::{RecvError, TryRecvError};
 use tokio::sync::{broadcast, Mutex};
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 use warp::Filter;
+use clipboard_common::{Content, Fingerprint};
+
+mod channels;
+mod clients;
+mod expiry;
+mod health;
+mod history;
+mod metrics;
+mod persist;
+mod shutdown;
+mod sse;
+mod web;
+use channels::{Channel, Channels, SharedChannel, SharedChannels};
+use clients::{ClientHandle, ClientStats, Clients};
+use health::Health;
+use metrics::Metrics;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct ClipboardData {
@@ -18,6 +36,55 @@ struct ClipboardData {
     // Metadata
     content_type: String, // "text", "html", "rtf", "image", "mixed"
     timestamp: u64,
+    // Assigned by the server when the update is published (see history.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    seq: Option<u64>,
+    // Seconds until the server clears the entry (see expiry.rs)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ttl: Option<u64>,
+    // Unix time at which the entry expires, set by the server from `ttl`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<u64>,
+    // Application the content was copied from, where the client's platform exposes it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source_app: Option<String>,
+    // Other MIME types carried verbatim for clients that can offer them, MIME type → base64 payload
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    extra_formats: BTreeMap<String, String>,
+    // Content fingerprint (clipboard_common::Fingerprint, hex), set by the server; identifies the entry in history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
+}
+
+impl ClipboardData {
+    fn content_fingerprint(&self) -> Fingerprint {
+        Fingerprint::of(&Content {
+            text: &self.content,
+            html: self.html.as_deref(),
+            rtf: self.rtf.as_deref(),
+            image: self.image.as_deref(),
+            extra: &self.extra_formats,
+        })
+    }
+
+    // Plain-text entry, as tests build them
+    #[cfg(test)]
+    fn text(content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            html: None,
+            rtf: None,
+            image: None,
+            content_type: "text".to_string(),
+            timestamp: 0,
+            seq: None,
+            ttl: None,
+            expires_at: None,
+            source_app: None,
+            extra_formats: BTreeMap::new(),
+            fingerprint: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,7 +94,39 @@ struct ClipboardMessage {
     data: ClipboardData,
 }
 
-type Clients = Arc<Mutex<HashMap<String, tokio::sync::mpsc::UnboundedSender<warp::ws::Message>>>>;
+// Sent when an entry was cleared or expired; clients still holding it clear their clipboard.
+// Clients send it too, to clear entry `seq` or, without one, the current entry.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ClearMessage {
+    #[serde(rename = "type")]
+    msg_type: String,
+    #[serde(default)]
+    seq: Option<u64>,
+}
+
+// What the broadcast channel carries to WebSocket and SSE subscribers.
+#[derive(Debug, Clone)]
+enum ClipboardEvent {
+    Update(Box<ClipboardData>),
+    Clear { seq: u64 },
+}
+
+impl ClipboardEvent {
+    // WebSocket frame for the event.
+    fn to_json(&self) -> serde_json::Result<String> {
+        match self {
+            ClipboardEvent::Update(data) => serde_json::to_string(&ClipboardMessage {
+                msg_type: "clipboard_update".to_string(),
+                data: ClipboardData::clone(data),
+            }),
+            ClipboardEvent::Clear { seq } => serde_json::to_string(&ClearMessage {
+                msg_type: "clipboard_clear".to_string(),
+                seq: Some(*seq),
+            }),
+        }
+    }
+}
+
 type ClipboardState = Arc<Mutex<Option<ClipboardData>>>;
 
 #[tokio::main]
@@ -35,41 +134,140 @@ async fn main() {
     // Initialize tracing
     tracing_subscriber::fmt::init();
 
-    // Shared state
-    let clipboard_state: ClipboardState = Arc::new(Mutex::new(None));
+    let history_size = std::env::var("CLIPBOARD_HISTORY_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(50);
+    // Slow clients that fall further behind than this are resynchronised with the latest state
+    let broadcast_capacity = std::env::var("CLIPBOARD_BROADCAST_CAPACITY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|&v| v > 0)
+        .unwrap_or(100);
+    let max_channels = env_u64("CLIPBOARD_MAX_CHANNELS").filter(|&v| v > 0).unwrap_or(100) as usize;
+    let channels: SharedChannels = Arc::new(Channels::new(history_size, broadcast_capacity, max_channels));
+
+    // Shared state, restored from the state file if one is configured
+    let state_file = persist::state_file();
+    if let Some(path) = state_file.as_deref() {
+        let restored = persist::load_state(path);
+        if !restored.is_empty() {
+            info!("Restored clipboard state of {} channel(s) from {}", restored.len(), path.display());
+        }
+        for (name, mut saved) in restored {
+            saved.current = saved.current.filter(|data| !expiry::is_expired(data));
+            saved.history.retain(|data| !expiry::is_expired(data));
+            let channel = channels.restore(&name, saved).await;
+            for data in channel.history.lock().await.entries() {
+                expiry::schedule(&data, &channel);
+            }
+        }
+    }
+    if let Some(path) = state_file.clone() {
+        persist::spawn_saver(path, channels.clone());
+    }
     let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
-    let (tx, _rx) = broadcast::channel::<ClipboardData>(100);
-    let broadcast_tx = Arc::new(tx);
+    let metrics = Arc::new(Metrics::new().expect("Failed to register metrics"));
+    let health = Arc::new(Health::new());
 
     // WebSocket route
     let clients_ws = clients.clone();
-    let clipboard_state_ws = clipboard_state.clone();
-    let broadcast_tx_ws = broadcast_tx.clone();
+    let metrics_ws = metrics.clone();
     let ws_route = warp::path("ws")
         .and(warp::ws())
+        .and(channels::filter(channels.clone()))
         .and(warp::any().map(move || clients_ws.clone()))
-        .and(warp::any().map(move || clipboard_state_ws.clone()))
-        .and(warp::any().map(move || broadcast_tx_ws.clone()))
+        .and(warp::any().map(move || metrics_ws.clone()))
         .and_then(ws_handler);
 
     // HTTP API route for setting clipboard
-    let clipboard_state_api = clipboard_state.clone();
-    let broadcast_tx_api = broadcast_tx.clone();
+    let metrics_api = metrics.clone();
     let api_route = warp::path!("api" / "clipboard")
         .and(warp::post())
         .and(warp::body::json())
-        .and(warp::any().map(move || clipboard_state_api.clone()))
-        .and(warp::any().map(move || broadcast_tx_api.clone()))
+        .and(channels::filter(channels.clone()))
+        .and(warp::any().map(move || metrics_api.clone()))
         .and_then(set_clipboard);
 
     // HTTP API route for getting clipboard
-    let clipboard_state_get = clipboard_state.clone();
+    let metrics_get = metrics.clone();
     let get_route = warp::path!("api" / "clipboard")
         .and(warp::get())
-        .and(warp::any().map(move || clipboard_state_get.clone()))
+        .and(channels::filter(channels.clone()))
+        .and(warp::any().map(move || metrics_get.clone()))
         .and_then(get_clipboard);
 
-    let routes = ws_route.or(api_route).or(get_route);
+    // HTTP API route for clearing the clipboard on every device
+    let metrics_delete = metrics.clone();
+    let delete_route = warp::path!("api" / "clipboard")
+        .and(warp::delete())
+        .and(warp::query::<ClearQuery>())
+        .and(channels::filter(channels.clone()))
+        .and(warp::any().map(move || metrics_delete.clone()))
+        .and_then(delete_clipboard);
+
+    // HTTP API route for the update history
+    let history_route = warp::path!("api" / "clipboard" / "history")
+        .and(warp::get())
+        .and(channels::filter(channels.clone()))
+        .and_then(history::get_history);
+
+    // Embedded web UI
+    let index_route = warp::path::end().and(warp::get()).and_then(web::index);
+
+    // Server-Sent Events stream of updates
+    let metrics_sse = metrics.clone();
+    let events_route = warp::path!("api" / "clipboard" / "events")
+        .and(warp::get())
+        .and(warp::header::optional::<u64>("last-event-id"))
+        .and(warp::query::<sse::EventsQuery>())
+        .and(channels::filter(channels.clone()))
+        .and(warp::any().map(move || metrics_sse.clone()))
+        .and_then(sse::events_handler);
+
+    // Per-client fan-out statistics
+    let clients_stats = clients.clone();
+    let clients_route = warp::path!("api" / "clients")
+        .and(warp::get())
+        .and(warp::any().map(move || clients_stats.clone()))
+        .and_then(clients::client_stats);
+
+    // Prometheus metrics
+    let metrics_scrape = metrics.clone();
+    let clients_metrics = clients.clone();
+    let metrics_route = warp::path!("metrics")
+        .and(warp::get())
+        .and(warp::any().map(move || metrics_scrape.clone()))
+        .and(warp::any().map(move || clients_metrics.clone()))
+        .and_then(metrics::metrics_handler);
+
+    // Liveness and readiness probes; never expose clipboard data
+    let health_live = health.clone();
+    let healthz_route = warp::path!("healthz")
+        .and(warp::get())
+        .and(warp::any().map(move || health_live.clone()))
+        .and_then(health::healthz);
+    let health_ready = health.clone();
+    let channels_ready = channels.clone();
+    let readyz_route = warp::path!("readyz")
+        .and(warp::get())
+        .and(warp::any().map(move || health_ready.clone()))
+        .and(warp::any().map(move || channels_ready.clone()))
+        .and_then(health::readyz);
+
+    let metrics_rejections = metrics.clone();
+    let routes = healthz_route
+        .or(readyz_route)
+        .or(ws_route)
+        .or(api_route)
+        .or(get_route)
+        .or(delete_route)
+        .or(events_route)
+        .or(history_route)
+        .or(index_route)
+        .or(clients_route)
+        .or(metrics_route)
+        .recover(move |err| metrics::handle_rejection(err, metrics_rejections.clone()));
 
     // Determine bind address - use 0.0.0.0 in container, 127.0.0.1 otherwise
     let bind_addr = if std::env::var("DOCKER_ENV").is_ok() {
@@ -80,52 +278,110 @@ async fn main() {
     
     info!("Starting clipboard server on {}:8080", 
           if bind_addr.0 == [0, 0, 0, 0] { "0.0.0.0" } else { "127.0.0.1" });
-    warp::serve(routes).run(bind_addr).await;
+    let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+    let server = match warp::serve(routes).try_bind_with_graceful_shutdown(bind_addr, async {
+        let _ = stop_rx.await;
+    }) {
+        Ok((_addr, server)) => server,
+        Err(e) => {
+            error!("Failed to bind {:?}: {}", bind_addr, e);
+            std::process::exit(1);
+        }
+    };
+    health.set_listening(true);
+    let mut server_task = tokio::spawn(server);
+
+    tokio::select! {
+        _ = shutdown::signal() => {}
+        _ = &mut server_task => {
+            warn!("Server stopped unexpectedly");
+            return;
+        }
+    }
+
+    // Stop accepting new connections, tell clients when to come back, then give
+    // in-flight requests and sockets a bounded amount of time to finish
+    let shutdown_timeout = Duration::from_secs(env_u64("CLIPBOARD_SHUTDOWN_TIMEOUT").unwrap_or(10));
+    let reconnect_after = env_u64("CLIPBOARD_RECONNECT_AFTER");
+    info!("Shutting down (timeout {:?})", shutdown_timeout);
+    health.set_listening(false);
+    let deadline = tokio::time::Instant::now() + shutdown_timeout;
+    let _ = stop_tx.send(());
+    shutdown::notify_clients(&clients, reconnect_after).await;
+
+    if tokio::time::timeout_at(deadline, server_task).await.is_err() {
+        warn!("In-flight requests did not finish within {:?}", shutdown_timeout);
+    }
+    if !shutdown::wait_for_clients(&clients, deadline).await {
+        warn!("Some WebSocket clients did not disconnect within {:?}", shutdown_timeout);
+    }
+
+    if let Some(path) = state_file.as_deref() {
+        match persist::save_state(path, &channels).await {
+            Ok(()) => info!("Saved clipboard state to {}", path.display()),
+            Err(e) => error!("Failed to save clipboard state to {}: {}", path.display(), e),
+        }
+    }
+    info!("Clipboard server stopped");
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    std::env::var(name).ok().and_then(|v| v.parse().ok())
 }
 
 async fn ws_handler(
     ws: warp::ws::Ws,
+    channel: SharedChannel,
     clients: Clients,
-    clipboard_state: ClipboardState,
-    broadcast_tx: Arc<broadcast::Sender<ClipboardData>>,
+    metrics: Arc<Metrics>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    Ok(ws.on_upgrade(move |socket| handle_client(socket, clients, clipboard_state, broadcast_tx)))
+    Ok(ws.on_upgrade(move |socket| handle_client(socket, channel, clients, metrics)))
 }
 
 async fn handle_client(
     ws: warp::ws::WebSocket,
+    channel: SharedChannel,
     clients: Clients,
-    clipboard_state: ClipboardState,
-    broadcast_tx: Arc<broadcast::Sender<ClipboardData>>,
+    metrics: Arc<Metrics>,
 ) {
     let client_id = uuid::Uuid::new_v4().to_string();
-    info!("New client connected: {}", client_id);
+    info!("New client connected: {} (channel '{}')", client_id, channel.name);
+    metrics.connected_clients.inc();
 
     let (mut ws_tx, mut ws_rx) = ws.split();
     let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let stats = Arc::new(ClientStats::default());
 
     // Add client to clients map
     {
         let mut clients_lock = clients.lock().await;
-        clients_lock.insert(client_id.clone(), tx);
+        clients_lock.insert(client_id.clone(), ClientHandle { tx, channel: channel.name.clone(), stats: stats.clone() });
     }
 
+    // Subscribe before reading the current state so no update slips in between
+    let mut broadcast_rx = channel.broadcast_tx.subscribe();
+
     // Send current clipboard state to new client
-    if let Some(current_data) = clipboard_state.lock().await.as_ref() {
+    let current = channel.state.lock().await.clone();
+    // Latest entry sent to this client, to clear after a lag if the channel was emptied meanwhile
+    let mut last_seq = current.as_ref().and_then(|data| data.seq);
+    if let Some(current_data) = current.as_ref() {
         let message = ClipboardMessage {
             msg_type: "clipboard_update".to_string(),
             data: current_data.clone(),
         };
         if let Ok(json) = serde_json::to_string(&message) {
-            let _ = ws_tx.send(warp::ws::Message::text(json)).await;
+            let len = json.len() as u64;
+            if ws_tx.send(warp::ws::Message::text(json)).await.is_ok() {
+                metrics.bytes_relayed.inc_by(len);
+            }
         }
     }
 
-    // Subscribe to broadcasts
-    let mut broadcast_rx = broadcast_tx.subscribe();
-
     // Spawn task to handle outgoing messages
     let client_id_clone = client_id.clone();
+    let channel_out = channel.clone();
+    let metrics_out = metrics.clone();
     let ws_tx_task = tokio::spawn(async move {
         loop {
             tokio::select! {
@@ -133,7 +389,8 @@ async fn handle_client(
                 msg = rx.recv() => {
                     match msg {
                         Some(message) => {
-                            if ws_tx.send(message).await.is_err() {
+                            let is_close = message.is_close();
+                            if ws_tx.send(message).await.is_err() || is_close {
                                 break;
                             }
                         }
@@ -142,19 +399,32 @@ async fn handle_client(
                 }
                 // Handle broadcast messages
                 broadcast_msg = broadcast_rx.recv() => {
-                    match broadcast_msg {
-                        Ok(data) => {
-                            let message = ClipboardMessage {
-                                msg_type: "clipboard_update".to_string(),
-                                data,
-                            };
-                            if let Ok(json) = serde_json::to_string(&message) {
-                                if ws_tx.send(warp::ws::Message::text(json)).await.is_err() {
-                                    break;
-                                }
+                    let event = match broadcast_msg {
+                        Ok(event) => event,
+                        Err(RecvError::Lagged(skipped)) => {
+                            // The client fell behind: drop everything still queued and send
+                            // the latest state once instead of replaying stale updates
+                            let skipped = skipped + drain_pending(&mut broadcast_rx);
+                            stats.record_lag(skipped);
+                            metrics_out.broadcast_lag_events.inc();
+                            warn!("Client {} lagged behind by {} updates, resynchronising", client_id_clone, skipped);
+                            match resync_event(&channel_out, last_seq).await {
+                                Some(event) => event,
+                                None => continue,
                             }
                         }
-                        Err(_) => break,
+                        Err(RecvError::Closed) => break,
+                    };
+                    if let ClipboardEvent::Update(data) = &event {
+                        last_seq = data.seq.or(last_seq);
+                    }
+                    stats.set_queue_depth(broadcast_rx.len());
+                    if let Ok(json) = event.to_json() {
+                        let len = json.len() as u64;
+                        if ws_tx.send(warp::ws::Message::text(json)).await.is_err() {
+                            break;
+                        }
+                        metrics_out.bytes_relayed.inc_by(len);
                     }
                 }
             }
@@ -170,13 +440,15 @@ async fn handle_client(
                     let text = msg.to_str().unwrap();
                     if let Ok(clipboard_msg) = serde_json::from_str::<ClipboardMessage>(text) {
                         if clipboard_msg.msg_type == "clipboard_set" {
-                            // Update clipboard state
-                            {
-                                let mut state = clipboard_state.lock().await;
-                                *state = Some(clipboard_msg.data.clone());
+                            metrics.record_update(&channel.name, "websocket", &clipboard_msg.data.content_type);
+                            publish(clipboard_msg.data, &channel).await;
+                        }
+                    } else if let Ok(clear_msg) = serde_json::from_str::<ClearMessage>(text) {
+                        if clear_msg.msg_type == "clipboard_clear" {
+                            let current = channel.state.lock().await.as_ref().and_then(|data| data.seq);
+                            if let Some(seq) = clear_msg.seq.or(current) {
+                                clear(seq, &format!("cleared by client {}", client_id), &channel).await;
                             }
-                            // Broadcast to all clients
-                            let _ = broadcast_tx.send(clipboard_msg.data);
                         }
                     }
                 }
@@ -196,15 +468,72 @@ async fn handle_client(
 
     // Cancel the outgoing message task
     ws_tx_task.abort();
+    metrics.connected_clients.dec();
+}
+
+// What a subscriber that lagged behind gets instead of the events it missed: the current entry,
+// or when the channel is empty, a clear of `last_seq`, the latest entry it was sent, since that
+// may have been cleared or expired among the missed events.
+async fn resync_event(channel: &Channel, last_seq: Option<u64>) -> Option<ClipboardEvent> {
+    match channel.state.lock().await.clone() {
+        Some(data) => Some(ClipboardEvent::Update(Box::new(data))),
+        None => last_seq.map(|seq| ClipboardEvent::Clear { seq }),
+    }
+}
+
+// Discard all queued broadcast updates, returning how many were dropped.
+fn drain_pending(broadcast_rx: &mut broadcast::Receiver<ClipboardEvent>) -> u64 {
+    let mut dropped = 0;
+    loop {
+        match broadcast_rx.try_recv() {
+            Ok(_) => dropped += 1,
+            Err(TryRecvError::Lagged(skipped)) => dropped += skipped,
+            Err(TryRecvError::Empty) | Err(TryRecvError::Closed) => break,
+        }
+    }
+    dropped
+}
+
+// Record an update in the channel's history, make it the current state and broadcast it to the channel.
+async fn publish(mut data: ClipboardData, channel: &SharedChannel) -> ClipboardData {
+    data.fingerprint = Some(data.content_fingerprint().to_string());
+    expiry::stamp(&mut data);
+    // Hold the state lock while assigning the sequence number so state and history agree
+    let mut state = channel.state.lock().await;
+    // Re-sending what the clipboard already holds (several clients copying the same thing, a
+    // client echoing an update back) is not a new update
+    if let Some(current) = state.as_mut().filter(|current| current.fingerprint == data.fingerprint) {
+        info!("Not republishing update {}: same content as entry {:?}", data.fingerprint.as_deref().unwrap_or_default(), current.seq);
+        // Its ttl still applies, so a new or shorter one takes effect; only the broadcast is skipped
+        if data.expires_at.is_some() && data.expires_at != current.expires_at {
+            if let Some(seq) = current.seq {
+                channel.history.lock().await.set_expiry(seq, data.ttl, data.expires_at);
+            }
+            current.ttl = data.ttl;
+            current.expires_at = data.expires_at;
+            channel.mark_changed();
+            expiry::schedule(current, channel);
+        }
+        return current.clone();
+    }
+    let data = channel.history.lock().await.push(data);
+    *state = Some(data.clone());
+    drop(state);
+    channel.mark_changed();
+    let _ = channel.broadcast_tx.send(ClipboardEvent::Update(Box::new(data.clone())));
+    expiry::schedule(&data, channel);
+    data
 }
 
 async fn set_clipboard(
     data: ClipboardData,
-    clipboard_state: ClipboardState,
-    broadcast_tx: Arc<broadcast::Sender<ClipboardData>>,
+    channel: SharedChannel,
+    metrics: Arc<Metrics>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    info!("Setting clipboard via HTTP API: {} chars, type: {}", 
-          data.content.len(), data.content_type);
+    let _timer = metrics.request_duration.with_label_values(&["set_clipboard"]).start_timer();
+    metrics.record_update(&channel.name, "http", &data.content_type);
+    info!("Setting clipboard via HTTP API: {} chars, type: {}, channel '{}'", 
+          data.content.len(), data.content_type, channel.name);
     
     if data.html.is_some() {
         info!("  - Contains HTML content");
@@ -216,22 +545,57 @@ async fn set_clipboard(
         info!("  - Contains image content");
     }
 
-    // Update clipboard state
-    {
-        let mut state = clipboard_state.lock().await;
-        *state = Some(data.clone());
+    let data = publish(data, &channel).await;
+
+    Ok(warp::reply::json(&data))
+}
+
+// Purge entry `seq` from the channel's current state and history and tell its clients to drop it.
+// The clear is broadcast even for entries already gone here, since clients may still hold them.
+async fn clear(seq: u64, reason: &str, channel: &Channel) {
+    let mut state = channel.state.lock().await;
+    if state.as_ref().and_then(|data| data.seq) == Some(seq) {
+        *state = None;
     }
+    channel.history.lock().await.remove(seq);
+    drop(state);
+    channel.mark_changed();
+    info!("Clipboard entry {} in channel '{}' {}", seq, channel.name, reason);
+    let _ = channel.broadcast_tx.send(ClipboardEvent::Clear { seq });
+}
 
-    // Broadcast to all WebSocket clients
-    let _ = broadcast_tx.send(data.clone());
+#[derive(Debug, Default, Deserialize)]
+struct ClearQuery {
+    // Entry to clear; the current one when absent
+    seq: Option<u64>,
+}
 
-    Ok(warp::reply::json(&data))
+async fn delete_clipboard(
+    query: ClearQuery,
+    channel: SharedChannel,
+    metrics: Arc<Metrics>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let _timer = metrics.request_duration.with_label_values(&["delete_clipboard"]).start_timer();
+    let current = channel.state.lock().await.as_ref().and_then(|data| data.seq);
+    let Some(seq) = query.seq.or(current) else {
+        return Ok(warp::reply::with_status(
+            warp::reply::json(&serde_json::json!({ "error": "clipboard is empty" })),
+            warp::http::StatusCode::NOT_FOUND,
+        ));
+    };
+    clear(seq, "cleared via HTTP API", &channel).await;
+    Ok(warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "seq": seq })),
+        warp::http::StatusCode::OK,
+    ))
 }
 
 async fn get_clipboard(
-    clipboard_state: ClipboardState,
+    channel: SharedChannel,
+    metrics: Arc<Metrics>,
 ) -> Result<impl warp::Reply, warp::Rejection> {
-    let state = clipboard_state.lock().await;
+    let _timer = metrics.request_duration.with_label_values(&["get_clipboard"]).start_timer();
+    let state = channel.state.lock().await;
     match state.as_ref() {
         Some(data) => Ok(warp::reply::json(data)),
         None => Ok(warp::reply::json(&ClipboardData {
@@ -241,6 +605,140 @@ async fn get_clipboard(
             image: None,
             content_type: "text".to_string(),
             timestamp: 0,
+            seq: None,
+            ttl: None,
+            expires_at: None,
+            source_app: None,
+            extra_formats: BTreeMap::new(),
+            fingerprint: None,
         })),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn channel() -> SharedChannel {
+        Channels::new(10, 16, 10).get("test").await.unwrap()
+    }
+
+    fn with_ttl(content: &str, ttl: u64) -> ClipboardData {
+        ClipboardData { ttl: Some(ttl), ..ClipboardData::text(content) }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resend_sets_expiry_without_republishing() {
+        let channel = channel().await;
+        let mut rx = channel.broadcast_tx.subscribe();
+        let first = publish(ClipboardData::text("x"), &channel).await;
+        assert_eq!(first.expires_at, None);
+
+        let again = publish(with_ttl("x", 60), &channel).await;
+        assert_eq!(again.seq, first.seq);
+        assert!(again.expires_at.is_some());
+        assert_eq!(channel.history.lock().await.entries()[0].expires_at, again.expires_at);
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Update(_))));
+        assert!(rx.try_recv().is_err(), "a re-send is not broadcast");
+
+        tokio::time::sleep(Duration::from_secs(61)).await;
+        assert!(channel.state.lock().await.is_none());
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Clear { seq }) if Some(seq) == first.seq));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shorter_ttl_on_resend_expires_sooner() {
+        let channel = channel().await;
+        publish(with_ttl("x", 600), &channel).await;
+        publish(with_ttl("x", 5), &channel).await;
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert!(channel.state.lock().await.is_none());
+        assert!(channel.history.lock().await.entries().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn longer_ttl_on_resend_is_not_cut_short_by_the_old_timer() {
+        let channel = channel().await;
+        publish(with_ttl("x", 5), &channel).await;
+        publish(with_ttl("x", 600), &channel).await;
+        tokio::time::sleep(Duration::from_secs(6)).await;
+        assert_eq!(channel.state.lock().await.as_ref().map(|data| data.content.as_str()), Some("x"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expired_entry_leaves_state_and_history() {
+        let channel = channel().await;
+        let mut rx = channel.broadcast_tx.subscribe();
+        let entry = publish(with_ttl("secret", 10), &channel).await;
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Update(_))));
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert!(channel.state.lock().await.is_none());
+        assert!(channel.history.lock().await.entries().is_empty());
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Clear { seq }) if Some(seq) == entry.seq));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn expiry_does_not_clear_a_newer_entry() {
+        let channel = channel().await;
+        let old = publish(with_ttl("secret", 10), &channel).await;
+        let new = publish(ClipboardData::text("public"), &channel).await;
+        let mut rx = channel.broadcast_tx.subscribe();
+
+        tokio::time::sleep(Duration::from_secs(11)).await;
+        assert_eq!(channel.state.lock().await.as_ref().and_then(|data| data.seq), new.seq);
+        let history: Vec<_> = channel.history.lock().await.entries().into_iter().map(|data| data.seq).collect();
+        assert_eq!(history, [new.seq]);
+        // Devices that still hold the old entry are told to clear it
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Clear { seq }) if Some(seq) == old.seq));
+    }
+
+
+    #[tokio::test]
+    async fn clear_removes_the_current_entry_and_tells_clients() {
+        let channel = channel().await;
+        let older = publish(ClipboardData::text("a"), &channel).await;
+        let current = publish(ClipboardData::text("b"), &channel).await;
+        let mut rx = channel.broadcast_tx.subscribe();
+
+        clear(current.seq.unwrap(), "cleared in test", &channel).await;
+        assert!(channel.state.lock().await.is_none());
+        let history: Vec<_> = channel.history.lock().await.entries().into_iter().map(|data| data.seq).collect();
+        assert_eq!(history, [older.seq]);
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Clear { seq }) if Some(seq) == current.seq));
+    }
+
+    #[tokio::test]
+    async fn clearing_an_older_entry_keeps_the_current_one() {
+        let channel = channel().await;
+        let older = publish(ClipboardData::text("a"), &channel).await;
+        let current = publish(ClipboardData::text("b"), &channel).await;
+
+        clear(older.seq.unwrap(), "cleared in test", &channel).await;
+        assert_eq!(channel.state.lock().await.as_ref().and_then(|data| data.seq), current.seq);
+        let history: Vec<_> = channel.history.lock().await.entries().into_iter().map(|data| data.seq).collect();
+        assert_eq!(history, [current.seq]);
+    }
+
+    #[tokio::test]
+    async fn delete_clears_the_current_entry_or_the_given_seq() {
+        use warp::Reply;
+
+        let channel = channel().await;
+        let metrics = Arc::new(Metrics::new().unwrap());
+        let delete = |seq| delete_clipboard(ClearQuery { seq }, channel.clone(), metrics.clone());
+        assert_eq!(delete(None).await.unwrap().into_response().status(), 404);
+
+        let older = publish(ClipboardData::text("a"), &channel).await;
+        publish(ClipboardData::text("b"), &channel).await;
+        let mut rx = channel.broadcast_tx.subscribe();
+        assert_eq!(delete(older.seq).await.unwrap().into_response().status(), 200);
+        assert!(matches!(rx.try_recv(), Ok(ClipboardEvent::Clear { seq }) if Some(seq) == older.seq));
+        assert!(channel.state.lock().await.is_some());
+
+        assert_eq!(delete(None).await.unwrap().into_response().status(), 200);
+        assert!(channel.state.lock().await.is_none());
+        assert_eq!(delete(None).await.unwrap().into_response().status(), 404);
+    }
+
+}
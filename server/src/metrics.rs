@@ -0,0 +1,123 @@
+// Prometheus metrics exposed on GET /metrics in the text exposition format.
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+use std::sync::Arc;
+
+use crate::clients::Clients;
+
+pub struct Metrics {
+    registry: Registry,
+    pub connected_clients: IntGauge,
+    pub updates_received: IntCounterVec,
+    pub bytes_relayed: IntCounter,
+    pub broadcast_lag_events: IntCounter,
+    pub rejected_requests: IntCounterVec,
+    pub request_duration: HistogramVec,
+    client_queue_depth_max: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> Result<Self, prometheus::Error> {
+        let registry = Registry::new_custom(Some("clipboard".into()), None)?;
+
+        let connected_clients = IntGauge::new("connected_clients", "Currently connected WebSocket clients")?;
+        let updates_received = IntCounterVec::new(
+            Opts::new("updates_received_total", "Clipboard updates received, by channel, source (http, websocket) and content type"),
+            &["channel", "source", "content_type"],
+        )?;
+        let bytes_relayed = IntCounter::new("bytes_relayed_total", "Bytes of clipboard updates and clears delivered to WebSocket and SSE clients")?;
+        let broadcast_lag_events = IntCounter::new("broadcast_lag_events_total", "Times a client fell behind the broadcast channel and was resynchronised")?;
+        let rejected_requests = IntCounterVec::new(
+            Opts::new("rejected_requests_total", "HTTP requests rejected, by reason"),
+            &["reason"],
+        )?;
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new("request_duration_seconds", "HTTP API request latency, by handler")
+                .buckets(vec![0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0]),
+            &["handler"],
+        )?;
+        // One series for all clients; GET /api/clients has the per-client numbers
+        let client_queue_depth_max = IntGauge::new("client_queue_depth_max", "Most broadcast updates waiting to be written to any one client")?;
+
+        registry.register(Box::new(connected_clients.clone()))?;
+        registry.register(Box::new(updates_received.clone()))?;
+        registry.register(Box::new(bytes_relayed.clone()))?;
+        registry.register(Box::new(broadcast_lag_events.clone()))?;
+        registry.register(Box::new(rejected_requests.clone()))?;
+        registry.register(Box::new(request_duration.clone()))?;
+        registry.register(Box::new(client_queue_depth_max.clone()))?;
+
+        Ok(Self {
+            registry,
+            connected_clients,
+            updates_received,
+            bytes_relayed,
+            broadcast_lag_events,
+            rejected_requests,
+            request_duration,
+            client_queue_depth_max,
+        })
+    }
+
+    pub fn record_update(&self, channel: &str, source: &str, content_type: &str) {
+        self.updates_received.with_label_values(&[channel, source, content_type]).inc();
+    }
+}
+
+pub async fn metrics_handler(
+    metrics: Arc<Metrics>,
+    clients: Clients,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let queue_depth_max = clients
+        .lock()
+        .await
+        .iter()
+        .map(|(id, handle)| handle.stats.snapshot(id, &handle.channel).queue_depth)
+        .max()
+        .unwrap_or(0);
+    metrics.client_queue_depth_max.set(queue_depth_max as i64);
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&metrics.registry.gather(), &mut buffer) {
+        tracing::warn!("Failed to encode metrics: {}", e);
+    }
+    Ok(warp::reply::with_header(buffer, "content-type", encoder.format_type().to_string()))
+}
+
+// Count rejected requests by reason and turn them into plain-text error replies.
+pub async fn handle_rejection(
+    err: warp::Rejection,
+    metrics: Arc<Metrics>,
+) -> Result<impl warp::Reply, std::convert::Infallible> {
+    use warp::http::StatusCode;
+
+    // Channel errors are checked before the method: the sibling routes on the same path add MethodNotAllowed
+    let (reason, status) = if err.is_not_found() {
+        ("not_found", StatusCode::NOT_FOUND)
+    } else if err.find::<crate::channels::InvalidChannel>().is_some() {
+        ("invalid_channel", StatusCode::BAD_REQUEST)
+    } else if err.find::<crate::channels::TooManyChannels>().is_some() {
+        ("too_many_channels", StatusCode::SERVICE_UNAVAILABLE)
+    } else if err.find::<warp::filters::body::BodyDeserializeError>().is_some() {
+        ("invalid_body", StatusCode::BAD_REQUEST)
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        ("payload_too_large", StatusCode::PAYLOAD_TOO_LARGE)
+    } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+        ("unsupported_media_type", StatusCode::UNSUPPORTED_MEDIA_TYPE)
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        ("method_not_allowed", StatusCode::METHOD_NOT_ALLOWED)
+    } else if err.find::<warp::reject::InvalidHeader>().is_some()
+        || err.find::<warp::reject::MissingHeader>().is_some()
+    {
+        ("invalid_header", StatusCode::BAD_REQUEST)
+    } else {
+        ("other", StatusCode::BAD_REQUEST)
+    };
+
+    metrics.rejected_requests.with_label_values(&[reason]).inc();
+    Ok(warp::reply::with_status(reason.replace('_', " "), status))
+}
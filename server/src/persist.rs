@@ -0,0 +1,126 @@
+// State file (CLIPBOARD_STATE_FILE): every channel's current entry and history, restored on start.
+// It is written shortly after each change, so a crash or kill loses at most the last SAVE_DELAY
+// of updates, and once more on shutdown.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tracing::{debug, warn};
+
+use crate::channels::{self, Channels, SharedChannels};
+use crate::ClipboardData;
+
+// Changes within this long of each other are written together
+const SAVE_DELAY: Duration = Duration::from_secs(1);
+
+pub fn state_file() -> Option<PathBuf> {
+    std::env::var_os("CLIPBOARD_STATE_FILE").map(PathBuf::from)
+}
+
+// What the state file holds for one channel.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SavedChannel {
+    pub current: Option<ClipboardData>,
+    // Oldest first
+    pub history: Vec<ClipboardData>,
+}
+
+impl SavedChannel {
+    fn entries(&self) -> impl Iterator<Item = &ClipboardData> {
+        self.current.iter().chain(&self.history)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedState {
+    channels: BTreeMap<String, SavedChannel>,
+}
+
+// Saved channels by name; empty when there is no usable state file.
+pub fn load_state(path: &Path) -> BTreeMap<String, SavedChannel> {
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return BTreeMap::new(),
+        Err(e) => {
+            warn!("Failed to read state file {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    let state = match serde_json::from_str::<SavedState>(&text) {
+        Ok(state) => state,
+        Err(e) => {
+            warn!("Ignoring corrupt state file {}: {}", path.display(), e);
+            return BTreeMap::new();
+        }
+    };
+    // Every saved entry was published, so it has a sequence number and fingerprint
+    if state.channels.values().flat_map(SavedChannel::entries).any(|data| data.seq.is_none() || data.fingerprint.is_none()) {
+        warn!("Ignoring corrupt state file {}: entry without a sequence number or fingerprint", path.display());
+        return BTreeMap::new();
+    }
+    state.channels.into_iter().filter(|(name, _)| channels::is_valid_name(name)).collect()
+}
+
+// Write via a temporary file so a crash mid-write never leaves a truncated state file.
+pub async fn save_state(path: &Path, channels: &Channels) -> std::io::Result<()> {
+    // The background saver and the final save on shutdown share the temporary file
+    static SAVING: Mutex<()> = Mutex::const_new(());
+    let _saving = SAVING.lock().await;
+    let mut state = SavedState { channels: BTreeMap::new() };
+    for channel in channels.all().await {
+        // History is read under the state lock, like publish writes it, so the two agree
+        let current = channel.state.lock().await;
+        let history = channel.history.lock().await.entries().into_iter().rev().collect();
+        let saved = SavedChannel { current: current.clone(), history };
+        drop(current);
+        state.channels.insert(channel.name.clone(), saved);
+    }
+    let json = serde_json::to_vec(&state)?;
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, json).await?;
+    tokio::fs::rename(&tmp, path).await
+}
+
+// Save the state file after every change, at most once per SAVE_DELAY.
+pub fn spawn_saver(path: PathBuf, channels: SharedChannels) {
+    tokio::spawn(async move {
+        loop {
+            channels.changed().await;
+            tokio::time::sleep(SAVE_DELAY).await;
+            match save_state(&path, &channels).await {
+                Ok(()) => debug!("Saved clipboard state to {}", path.display()),
+                Err(e) => warn!("Failed to save clipboard state to {}: {}", path.display(), e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn restart_restores_current_entry_and_history() {
+        let path = std::env::temp_dir().join(format!("clipboard-state-{}.json", std::process::id()));
+        let channels = Channels::new(10, 16, 10);
+        let channel = channels.get("test").await.unwrap();
+        for content in ["a", "b"] {
+            crate::publish(ClipboardData::text(content), &channel).await;
+        }
+        save_state(&path, &channels).await.unwrap();
+
+        let restored = Channels::new(10, 16, 10);
+        for (name, saved) in load_state(&path) {
+            restored.restore(&name, saved).await;
+        }
+        std::fs::remove_file(&path).unwrap();
+        let channel = restored.get("test").await.unwrap();
+        assert_eq!(channel.state.lock().await.as_ref().unwrap().content, "b");
+        let seqs: Vec<_> = channel.history.lock().await.entries().iter().map(|data| data.seq.unwrap()).collect();
+        assert_eq!(seqs, [2, 1]);
+        // Numbering carries on where it left off
+        assert_eq!(crate::publish(ClipboardData::text("c"), &channel).await.seq, Some(3));
+    }
+}
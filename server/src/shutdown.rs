@@ -0,0 +1,74 @@
+// Graceful shutdown: signal handling and client notification (the state file is in persist.rs).
+
+use futures_util::future::FutureExt;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::clients::Clients;
+
+// Resolves on Ctrl+C, or SIGTERM on Unix (what `docker stop` sends).
+pub async fn signal() {
+    let ctrl_c = tokio::signal::ctrl_c().map(|_| ());
+
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = ctrl_c => info!("Received Ctrl+C"),
+                    _ = sigterm.recv() => info!("Received SIGTERM"),
+                }
+            }
+            Err(e) => {
+                warn!("Failed to install SIGTERM handler: {}", e);
+                ctrl_c.await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        ctrl_c.await;
+        info!("Received Ctrl+C");
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ShutdownMessage {
+    #[serde(rename = "type")]
+    msg_type: &'static str,
+    // Seconds clients should wait before reconnecting
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reconnect_after: Option<u64>,
+}
+
+// Send a `server_shutdown` frame followed by a close frame to every connected client.
+pub async fn notify_clients(clients: &Clients, reconnect_after: Option<u64>) {
+    let message = ShutdownMessage { msg_type: "server_shutdown", reconnect_after };
+    let json = match serde_json::to_string(&message) {
+        Ok(json) => json,
+        Err(_) => return,
+    };
+    let clients = clients.lock().await;
+    info!("Notifying {} client(s) of shutdown", clients.len());
+    for handle in clients.values() {
+        let _ = handle.tx.send(warp::ws::Message::text(json.clone()));
+        let _ = handle.tx.send(warp::ws::Message::close());
+    }
+}
+
+// Wait until every client has disconnected or the deadline passes.
+pub async fn wait_for_clients(clients: &Clients, deadline: Instant) -> bool {
+    loop {
+        if clients.lock().await.is_empty() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
@@ -0,0 +1,220 @@
+// Server-Sent Events stream of clipboard updates for read-only consumers
+// (browser dashboards, `curl -N` in shell scripts). Expired entries arrive as
+// `clipboard_clear` events carrying only the sequence number.
+
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use warp::sse::Event;
+
+use crate::channels::SharedChannel;
+use crate::metrics::Metrics;
+use crate::{ClipboardData, ClipboardEvent};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EventsQuery {
+    // Comma-separated content types to deliver, e.g. `html,mixed`; all when absent
+    content_type: Option<String>,
+}
+
+impl EventsQuery {
+    fn content_types(&self) -> Option<Vec<String>> {
+        self.content_type.as_ref().map(|types| {
+            types
+                .split(',')
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect()
+        })
+    }
+}
+
+struct EventStream {
+    // Replayed entries (history or current state) delivered before live updates
+    backlog: VecDeque<ClipboardData>,
+    broadcast_rx: broadcast::Receiver<ClipboardEvent>,
+    channel: SharedChannel,
+    // Highest sequence number delivered so far, to skip live duplicates of the backlog
+    last_seq: u64,
+}
+
+impl EventStream {
+    // Backlog for a new subscriber: missed entries when resuming, otherwise the current state.
+    async fn start(channel: SharedChannel, last_event_id: Option<u64>) -> Self {
+        // Subscribe before reading history so nothing published in between is lost
+        let broadcast_rx = channel.broadcast_tx.subscribe();
+        let (backlog, last_seq) = match last_event_id {
+            Some(seq) => {
+                let history = channel.history.lock().await;
+                // An ID newer than any issued here was handed out before a restart without a
+                // state file, which numbers entries from 1 again: replay everything retained
+                let seq = if seq > history.last_seq() { 0 } else { seq };
+                (history.since(seq).into(), seq)
+            }
+            None => (channel.state.lock().await.clone().into_iter().collect(), 0),
+        };
+        Self { backlog, broadcast_rx, channel, last_seq }
+    }
+}
+
+pub async fn events_handler(
+    last_event_id: Option<u64>,
+    query: EventsQuery,
+    channel: SharedChannel,
+    metrics: Arc<Metrics>,
+) -> Result<impl warp::Reply, warp::Rejection> {
+    let state = EventStream::start(channel, last_event_id).await;
+    let content_types = query.content_types();
+    let events = clipboard_stream(state)
+        .filter(move |event| {
+            let wanted = match event {
+                ClipboardEvent::Update(data) => content_types
+                    .as_ref()
+                    .is_none_or(|types| types.contains(&data.content_type)),
+                ClipboardEvent::Clear { .. } => true,
+            };
+            std::future::ready(wanted)
+        })
+        .map(move |event| {
+            let (event, len) = to_event(&event);
+            metrics.bytes_relayed.inc_by(len as u64);
+            Ok::<Event, Infallible>(event)
+        });
+
+    Ok(warp::sse::reply(
+        warp::sse::keep_alive().interval(Duration::from_secs(15)).stream(events),
+    ))
+}
+
+fn clipboard_stream(state: EventStream) -> impl Stream<Item = ClipboardEvent> {
+    stream::unfold(state, |mut state| async move {
+        loop {
+            let data = match state.backlog.pop_front() {
+                Some(data) => data,
+                None => match state.broadcast_rx.recv().await {
+                    Ok(ClipboardEvent::Update(data)) => *data,
+                    Ok(event @ ClipboardEvent::Clear { .. }) => return Some((event, state)),
+                    // Like WebSocket clients, a lagging consumer gets the latest state only
+                    Err(RecvError::Lagged(_)) => match crate::resync_event(&state.channel, Some(state.last_seq).filter(|seq| *seq > 0)).await {
+                        Some(ClipboardEvent::Update(data)) => *data,
+                        Some(event) => return Some((event, state)),
+                        None => continue,
+                    },
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            if let Some(seq) = data.seq {
+                if seq <= state.last_seq {
+                    continue;
+                }
+                state.last_seq = seq;
+            }
+            return Some((ClipboardEvent::Update(Box::new(data)), state));
+        }
+    })
+}
+
+// SSE event for `event`, with the size of its data.
+fn to_event(event: &ClipboardEvent) -> (Event, usize) {
+    let (event, json) = match event {
+        ClipboardEvent::Update(data) => {
+            let event = Event::default().event("clipboard_update");
+            let event = match data.seq {
+                Some(seq) => event.id(seq.to_string()),
+                None => event,
+            };
+            (event, serde_json::to_string(data))
+        }
+        ClipboardEvent::Clear { seq } => {
+            (Event::default().event("clipboard_clear"), serde_json::to_string(&serde_json::json!({ "seq": seq })))
+        }
+    };
+    match json {
+        Ok(json) => {
+            let len = json.len();
+            (event.data(json), len)
+        }
+        Err(_) => (Event::default().comment("serialization error"), 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::Channels;
+    use futures_util::FutureExt;
+    use std::pin::pin;
+
+    async fn channel_with(contents: &[&str]) -> SharedChannel {
+        let channel = Channels::new(10, 16, 10).get("test").await.unwrap();
+        for content in contents {
+            crate::publish(ClipboardData::text(content), &channel).await;
+        }
+        channel
+    }
+
+    // Sequence numbers of the updates delivered without waiting
+    fn delivered(stream: &mut (impl Stream<Item = ClipboardEvent> + Unpin)) -> Vec<u64> {
+        let mut seqs = Vec::new();
+        while let Some(Some(event)) = stream.next().now_or_never() {
+            match event {
+                ClipboardEvent::Update(data) => seqs.push(data.seq.unwrap()),
+                ClipboardEvent::Clear { seq } => panic!("unexpected clear of {}", seq),
+            }
+        }
+        seqs
+    }
+
+    #[tokio::test]
+    async fn without_last_event_id_starts_with_the_current_entry() {
+        let channel = channel_with(&["a", "b"]).await;
+        let mut stream = pin!(clipboard_stream(EventStream::start(channel, None).await));
+        assert_eq!(delivered(&mut stream), [2]);
+    }
+
+    #[tokio::test]
+    async fn resume_replays_missed_entries_then_live_updates() {
+        let channel = channel_with(&["a", "b", "c"]).await;
+        let mut stream = pin!(clipboard_stream(EventStream::start(channel.clone(), Some(1)).await));
+        assert_eq!(delivered(&mut stream), [2, 3]);
+        crate::publish(ClipboardData::text("d"), &channel).await;
+        assert_eq!(delivered(&mut stream), [4]);
+    }
+
+    #[tokio::test]
+    async fn resume_at_the_latest_entry_only_gets_live_updates() {
+        let channel = channel_with(&["a", "b"]).await;
+        let mut stream = pin!(clipboard_stream(EventStream::start(channel.clone(), Some(2)).await));
+        assert_eq!(delivered(&mut stream), Vec::<u64>::new());
+        crate::publish(ClipboardData::text("c"), &channel).await;
+        assert_eq!(delivered(&mut stream), [3]);
+    }
+
+    #[tokio::test]
+    async fn last_event_id_from_before_a_restart_replays_everything() {
+        // The consumer saw entry 40 before the server restarted and began counting at 1 again
+        let channel = channel_with(&["a", "b"]).await;
+        let mut stream = pin!(clipboard_stream(EventStream::start(channel.clone(), Some(40)).await));
+        assert_eq!(delivered(&mut stream), [1, 2]);
+        crate::publish(ClipboardData::text("c"), &channel).await;
+        assert_eq!(delivered(&mut stream), [3]);
+    }
+
+    #[tokio::test]
+    async fn lagging_consumer_of_an_emptied_channel_is_told_to_clear() {
+        // Room for one event only, so an update and its clear overflow the queue
+        let channel = Channels::new(10, 1, 10).get("test").await.unwrap();
+        crate::publish(ClipboardData::text("a"), &channel).await;
+        let mut stream = pin!(clipboard_stream(EventStream::start(channel.clone(), None).await));
+        assert_eq!(delivered(&mut stream), [1]);
+        let secret = crate::publish(ClipboardData::text("secret"), &channel).await;
+        crate::clear(secret.seq.unwrap(), "cleared in test", &channel).await;
+        // The consumer still holds entry 1; the clear of the entry it never saw is what was kept queued
+        assert!(matches!(stream.next().now_or_never(), Some(Some(ClipboardEvent::Clear { seq: 1 }))));
+        assert!(matches!(stream.next().now_or_never(), Some(Some(ClipboardEvent::Clear { seq: 2 }))));
+    }
+}
@@ -0,0 +1,14 @@
+// Built-in web UI for devices without clipboard-client (phones, locked-down laptops).
+// The page is compiled into the binary and talks to the regular HTTP API and /ws.
+
+const INDEX_HTML: &str = include_str!("../static/index.html");
+
+// Scripts and styles are inline; everything else (images) must be data: URLs.
+const CONTENT_SECURITY_POLICY: &str = "default-src 'self'; script-src 'unsafe-inline'; \
+    style-src 'unsafe-inline'; img-src data:; frame-src 'self' about:; connect-src 'self'";
+
+pub async fn index() -> Result<impl warp::Reply, warp::Rejection> {
+    let reply = warp::reply::html(INDEX_HTML);
+    let reply = warp::reply::with_header(reply, "content-security-policy", CONTENT_SECURITY_POLICY);
+    Ok(warp::reply::with_header(reply, "x-content-type-options", "nosniff"))
+}